@@ -0,0 +1,350 @@
+use std::{sync::Arc, time::Duration};
+
+use pyo3::{
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+use tokio::sync::mpsc::UnboundedReceiver;
+
+use crate::{
+    connection::Connector,
+    consumer::{Handler, SubscribeSpec, Subscriber, SubscriptionState},
+    exceptions::AppError,
+    field_table, field_table_arg,
+    message::Message,
+    publisher::Publisher,
+    rpc::{PendingReply, RemoteError, RpcClient},
+    streaming::ReplyStream,
+    topology::{ExchangeKind, ExchangeSpec, QueueOptions, Topology},
+    Config, ContentEncoding, DeliveryMode, Payload, PublishBody, QoSConfig,
+};
+
+/// Connection, publisher and consumers shared by `AsyncEventbus` and
+/// `SyncEventbus`, which only differ in how they wait for these futures.
+#[derive(Clone)]
+pub(crate) struct Bus {
+    pub(crate) connector: Arc<Connector>,
+    pub(crate) publisher: Arc<Publisher>,
+    pub(crate) rpc: Arc<RpcClient>,
+    pub(crate) subscriber: Arc<Subscriber>,
+    pub(crate) rpc_server: Arc<Subscriber>,
+    pub(crate) rpc_exchange_name: String,
+    pub(crate) topology: Arc<Topology>,
+}
+
+pub(crate) struct PublishRequest {
+    exchange_name: String,
+    routing_key: String,
+    message: Message,
+    exchange: Option<ExchangeSpec>,
+    timeout: Option<Duration>,
+}
+
+impl PublishRequest {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new<'py>(
+        exchange_name: &str,
+        routing_key: &str,
+        body: PublishBody<'py>,
+        content_type: Option<&str>,
+        content_encoding: ContentEncoding,
+        command_timeout: Option<u64>,
+        delivery_mode: DeliveryMode,
+        expiration: Option<u32>,
+        headers: Option<Bound<'py, PyDict>>,
+        message_id: Option<String>,
+        correlation_id: Option<String>,
+        priority: Option<u8>,
+        timestamp: Option<u64>,
+        app_id: Option<String>,
+        r#type: Option<String>,
+        user_id: Option<String>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<Self> {
+        // Without an exchange type the exchange is expected to exist already.
+        let exchange = match exchange_type {
+            Some(kind) => Some(ExchangeSpec::durable(
+                exchange_name,
+                kind.into_string(),
+                field_table_arg(exchange_arguments)?,
+            )),
+            None => None,
+        };
+        // Properties set on a `Message` body take precedence, the keyword
+        // arguments only fill in what it leaves unset.
+        let mut message = match body {
+            PublishBody::Message(message) => message.clone(),
+            PublishBody::Payload(Payload::Bytes(b)) => Message::from_body(b.as_bytes().into(), None),
+            PublishBody::Payload(Payload::Str(s)) => Message::from_body(s.to_str()?.as_bytes().into(), None),
+        };
+        // Bodies are compressed on publish according to `content_encoding`.
+        if message.content_encoding.is_none() {
+            message.content_encoding = content_encoding.as_property().map(str::to_owned);
+        }
+        if message.headers.is_none() {
+            message.headers = headers.map(|h| field_table::from_py(&h)).transpose()?;
+        }
+        message.content_type = message.content_type.or(content_type.map(str::to_owned));
+        message.delivery_mode = message.delivery_mode.or(Some(delivery_mode));
+        message.expiration = message.expiration.or(expiration.map(|e| e.to_string()));
+        message.message_id = message.message_id.or(message_id);
+        message.correlation_id = message.correlation_id.or(correlation_id);
+        message.priority = message.priority.or(priority);
+        message.timestamp = message.timestamp.or(timestamp);
+        message.app_id = message.app_id.or(app_id);
+        message.message_type = message.message_type.or(r#type);
+        message.user_id = message.user_id.or(user_id);
+        Ok(Self {
+            exchange_name: exchange_name.to_owned(),
+            routing_key: routing_key.to_owned(),
+            message,
+            exchange,
+            timeout: command_timeout.map(Duration::from_secs),
+        })
+    }
+}
+
+pub(crate) struct RpcRequest {
+    exchange_name: String,
+    routing_key: String,
+    message: Message,
+    response_timeout: Duration,
+    connection_timeout: Option<Duration>,
+    exchange: Option<ExchangeSpec>,
+}
+
+impl RpcRequest {
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new<'py>(
+        exchange_name: &str,
+        routing_key: &str,
+        body: Payload<'py>,
+        content_type: &str,
+        content_encoding: ContentEncoding,
+        response_timeout: u32,
+        connection_timeout: Option<u64>,
+        delivery_mode: DeliveryMode,
+        expiration: Option<u32>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<Self> {
+        let exchange = match exchange_type {
+            Some(kind) => Some(ExchangeSpec::durable(
+                exchange_name,
+                kind.into_string(),
+                field_table_arg(exchange_arguments)?,
+            )),
+            None => None,
+        };
+        let body = match body {
+            Payload::Bytes(b) => b.as_bytes().to_vec(),
+            Payload::Str(s) => s.to_str()?.as_bytes().to_vec(),
+        };
+        let mut message = Message::from_body(body.into(), Some(content_type.to_owned()));
+        message.content_encoding = content_encoding.as_property().map(str::to_owned);
+        message.delivery_mode = Some(delivery_mode);
+        message.expiration = expiration.map(|e| e.to_string());
+        Ok(Self {
+            exchange_name: exchange_name.to_owned(),
+            routing_key: routing_key.to_owned(),
+            message,
+            response_timeout: Duration::from_millis(response_timeout.into()),
+            connection_timeout: connection_timeout.map(Duration::from_secs),
+            exchange,
+        })
+    }
+}
+
+/// What `subscribe`, `consume` and `provide_resource` bind when given these
+/// keyword arguments; the exchange defaults to a durable topic exchange.
+pub(crate) fn subscribe_spec(
+    exchange_name: &str,
+    routing_key: &str,
+    queue_options: Option<QueueOptions>,
+    exchange_type: Option<ExchangeKind>,
+    exchange_arguments: Option<Bound<'_, PyDict>>,
+) -> PyResult<SubscribeSpec> {
+    Ok(SubscribeSpec {
+        exchange: ExchangeSpec::durable(
+            exchange_name,
+            exchange_type.map_or_else(|| "topic".to_owned(), ExchangeKind::into_string),
+            field_table_arg(exchange_arguments)?,
+        ),
+        routing_key: routing_key.to_owned(),
+        queue_options: queue_options.unwrap_or_default(),
+        prefetch: None,
+        manual_settle: false,
+        include_traceback: false,
+    })
+}
+
+impl Bus {
+    pub(crate) fn new(config: Config, qos_config: QoSConfig) -> PyResult<Self> {
+        // The config's fields may have been set after it was built.
+        config.validate()?;
+        let rt = pyo3_async_runtimes::tokio::get_runtime();
+
+        let _guard = rt.enter();
+        let connector = Arc::new(Connector::new(&config));
+        let publisher = Arc::new(Publisher::new(Arc::clone(&connector), qos_config.pub_confirm));
+        let subscriber = Arc::new(Subscriber::new(
+            Arc::clone(&connector),
+            config.options.queue_name.clone(),
+            &qos_config,
+        ));
+        let rpc_server = Arc::new(Subscriber::rpc_server(
+            Arc::clone(&connector),
+            config.options.rpc_queue_name.clone(),
+            &qos_config,
+        ));
+        let rpc = Arc::new(RpcClient::new(Arc::clone(&connector), &qos_config));
+        let rpc_exchange_name = config.options.rpc_exchange_name.clone();
+        let topology = Arc::new(Topology::new(Arc::clone(&connector)));
+        Ok(Self {
+            connector,
+            publisher,
+            rpc,
+            subscriber,
+            rpc_server,
+            rpc_exchange_name,
+            topology,
+        })
+    }
+
+    pub(crate) async fn publish(&self, request: PublishRequest) -> PyResult<()> {
+        let PublishRequest {
+            exchange_name,
+            routing_key,
+            message,
+            exchange,
+            timeout,
+        } = request;
+        let body = message.wire_body()?;
+        if let Some(exchange) = &exchange {
+            self.publisher.declare_exchange(exchange, timeout).await?;
+        }
+        self.publisher
+            .publish(&exchange_name, &routing_key, body, message.properties(), timeout)
+            .await
+            .map_err(Into::into)
+    }
+
+    pub(crate) async fn rpc_client(&self, request: RpcRequest) -> PyResult<Py<PyAny>> {
+        let reply = self.rpc_call(request).await?;
+        Python::attach(|py| Ok(PyBytes::new(py, &reply.body).into_any().unbind()))
+    }
+
+    /// Sends the request and returns the whole reply, raising the error of a
+    /// failed provider handler.
+    pub(crate) async fn rpc_call(&self, mut request: RpcRequest) -> PyResult<Message> {
+        self.declare_request_exchange(&mut request).await?;
+        let reply = self
+            .rpc
+            .call(
+                &request.exchange_name,
+                &request.routing_key,
+                request.message,
+                request.response_timeout,
+                request.connection_timeout,
+            )
+            .await?;
+        match RemoteError::from_reply(&reply) {
+            Some(error) => Err(error.into()),
+            None => Ok(reply),
+        }
+    }
+
+    /// Sends the request once and returns every reply that arrived before its
+    /// response timeout, error replies included.
+    pub(crate) async fn rpc_gather(&self, mut request: RpcRequest, max_replies: Option<usize>) -> PyResult<Vec<Message>> {
+        self.declare_request_exchange(&mut request).await?;
+        self.rpc
+            .gather(
+                &request.exchange_name,
+                &request.routing_key,
+                request.message,
+                request.response_timeout,
+                max_replies,
+                request.connection_timeout,
+            )
+            .await
+            .map_err(Into::into)
+    }
+
+    /// A stream of the reply chunks; the request is sent on the first
+    /// iteration. The response timeout applies to each chunk.
+    pub(crate) fn rpc_stream(&self, request: RpcRequest) -> ReplyStream {
+        let chunk_timeout = request.response_timeout;
+        ReplyStream::new(self.clone(), request, chunk_timeout)
+    }
+
+    pub(crate) async fn open_stream(
+        &self,
+        mut request: RpcRequest,
+    ) -> PyResult<(UnboundedReceiver<Message>, PendingReply)> {
+        self.declare_request_exchange(&mut request).await?;
+        self.rpc
+            .send(
+                &request.exchange_name,
+                &request.routing_key,
+                request.message,
+                request.connection_timeout,
+            )
+            .await
+            .map_err(Into::into)
+    }
+
+    async fn declare_request_exchange(&self, request: &mut RpcRequest) -> Result<(), AppError> {
+        match request.exchange.take() {
+            Some(exchange) => {
+                self.topology
+                    .declare_exchange(exchange, request.connection_timeout)
+                    .await
+            }
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) async fn subscribe(
+        &self,
+        spec: SubscribeSpec,
+        handler: Handler,
+        process_timeout: Option<u64>,
+        command_timeout: Option<u64>,
+    ) -> Result<Arc<SubscriptionState>, AppError> {
+        self.subscriber
+            .subscribe(
+                spec,
+                handler,
+                process_timeout.map(Duration::from_secs),
+                command_timeout.map(Duration::from_secs),
+            )
+            .await
+    }
+
+    pub(crate) async fn provide_resource(
+        &self,
+        spec: SubscribeSpec,
+        handler: Handler,
+        process_timeout: Option<u64>,
+        command_timeout: Option<u64>,
+    ) -> Result<Arc<SubscriptionState>, AppError> {
+        self.rpc_server
+            .subscribe(
+                spec,
+                handler,
+                process_timeout.map(Duration::from_secs),
+                command_timeout.map(Duration::from_secs),
+            )
+            .await
+    }
+
+    pub(crate) async fn dispose(&self) -> PyResult<()> {
+        self.subscriber.dispose();
+        self.rpc_server.dispose();
+        self.rpc.dispose().await;
+        self.connector.dispose().await;
+        Ok(())
+    }
+}
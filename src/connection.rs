@@ -0,0 +1,303 @@
+use std::{
+    collections::hash_map::RandomState,
+    hash::{BuildHasher, Hasher},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex as StdMutex,
+    },
+    time::Duration,
+};
+
+use amqp_client_rust::amqprs::{
+    callbacks::ChannelCallback,
+    channel::Channel,
+    connection::{Connection, OpenConnectionArguments},
+    error::Error as AmqprsError,
+    security::SecurityCredentials,
+    Ack, BasicProperties, Cancel, CloseChannel, Nack, Return,
+};
+use async_trait::async_trait;
+use tokio::{sync::Mutex, task::JoinHandle, time::Instant};
+
+use crate::{
+    credentials::{CredentialsProvider, Secret},
+    exceptions::{self, AppError, ErrorType},
+    AuthMechanism, Config, Endpoint, HostSelection,
+};
+
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(5);
+/// Keeps a provider that hands out expired tokens from being called in a
+/// tight loop.
+const MIN_REFRESH_DELAY: Duration = Duration::from_secs(5);
+
+/// Lazily opened connection every publisher, consumer and RPC client of a
+/// bus shares. A closed connection is re-opened on the next request, on the
+/// next node when there are several.
+pub(crate) struct Connector {
+    endpoints: Vec<(String, OpenConnectionArguments)>,
+    host_selection: HostSelection,
+    /// Index of the endpoint last connected to.
+    current: AtomicUsize,
+    /// Bounds each connection attempt, so an unresponsive node does not use
+    /// up the whole reconnect deadline.
+    attempt_timeout: Option<Duration>,
+    connection: Mutex<Option<Connection>>,
+    disposed: AtomicBool,
+    /// Supplies the password on every connect, replacing the static one.
+    credentials: Option<CredentialsProvider>,
+    auth_mechanism: AuthMechanism,
+    username: String,
+    /// Pushes refreshed tokens to the open connection.
+    refresh: StdMutex<Option<JoinHandle<()>>>,
+}
+
+impl Connector {
+    pub(crate) fn new(config: &Config) -> Self {
+        let endpoints = config
+            .endpoints()
+            .iter()
+            .map(|endpoint| {
+                let label = format!("{}:{}", endpoint.host(), endpoint.port());
+                (label, Self::connection_args(config, endpoint))
+            })
+            .collect();
+        Self {
+            endpoints,
+            host_selection: config.host_selection.clone(),
+            current: AtomicUsize::new(0),
+            attempt_timeout: config.connection_timeout.map(Duration::from_millis),
+            connection: Mutex::new(None),
+            disposed: AtomicBool::new(false),
+            credentials: config.credentials_provider.clone(),
+            auth_mechanism: config.auth_mechanism.clone(),
+            username: config.username.clone(),
+            refresh: StdMutex::new(None),
+        }
+    }
+
+    fn sasl(mechanism: &AuthMechanism, username: &str, password: &str) -> SecurityCredentials {
+        match mechanism {
+            AuthMechanism::Plain => SecurityCredentials::new_plain(username, password),
+            AuthMechanism::AMQPlain => SecurityCredentials::new_amqplain(username, password),
+            AuthMechanism::External => SecurityCredentials::new_external(),
+        }
+    }
+
+    fn connection_args(config: &Config, endpoint: &Endpoint) -> OpenConnectionArguments {
+        let mut args = OpenConnectionArguments::new(
+            endpoint.host(),
+            endpoint.port(),
+            &config.username,
+            &config.password,
+        );
+        args.virtual_host(&config.virtual_host);
+        args.credentials(Self::sasl(&config.auth_mechanism, &config.username, &config.password));
+        if let Some(heartbeat) = config.heartbeat {
+            args.heartbeat(heartbeat);
+        }
+        if let Some(connection_name) = &config.connection_name {
+            args.connection_name(connection_name);
+        }
+        if let Some(tls_adaptor) = &config.tls_adaptor {
+            args.tls_adaptor(tls_adaptor.for_domain(endpoint.tls_domain()));
+        }
+        args.finish()
+    }
+
+    /// Returns the open connection, reconnecting with backoff until `timeout`
+    /// elapses. `None` waits indefinitely.
+    pub(crate) async fn connection(&self, timeout: Option<Duration>) -> Result<Connection, AppError> {
+        if let Some(connection) = self.connection.lock().await.as_ref().filter(|c| c.is_open()) {
+            return Ok(connection.clone());
+        }
+        let deadline = timeout.map(|t| Instant::now() + t);
+        let mut delay = Duration::from_millis(100);
+        loop {
+            let mut errors = Vec::new();
+            // A fresh token for every round, as the last one may have expired.
+            // The provider may call into Python, so it runs without the lock.
+            let secret = match &self.credentials {
+                Some(provider) => match provider.fetch().await {
+                    Ok(secret) => Some(secret),
+                    Err(e) => {
+                        errors.push(e.to_string());
+                        None
+                    }
+                },
+                None => None,
+            };
+            let mut guard = self.connection.lock().await;
+            // Another caller may have reconnected meanwhile.
+            if let Some(connection) = guard.as_ref().filter(|c| c.is_open()) {
+                return Ok(connection.clone());
+            }
+            // A connection that dropped sends the first attempt to another node.
+            let skip_current = guard.take().is_some();
+            let order = if self.credentials.is_some() && secret.is_none() {
+                Vec::new()
+            } else {
+                self.attempt_order(skip_current)
+            };
+            for index in order {
+                if self.disposed.load(Ordering::Acquire) {
+                    return Err(AppError::new(ErrorType::ConnectionClosed, "eventbus was disposed"));
+                }
+                let (label, args) = &self.endpoints[index];
+                match self.open(args, secret.as_ref()).await {
+                    Ok(connection) => {
+                        self.current.store(index, Ordering::Relaxed);
+                        *guard = Some(connection.clone());
+                        if let Some(secret) = secret {
+                            self.refresh_secret(connection.clone(), secret);
+                        }
+                        return Ok(connection);
+                    }
+                    Err(e) => errors.push(format!("{label}: {e}")),
+                }
+            }
+            drop(guard);
+            if deadline.is_some_and(|d| Instant::now() + delay > d) {
+                return Err(AppError::new(
+                    ErrorType::AutoReconnect,
+                    format!("could not connect to the broker: {}", errors.join("; ")),
+                ));
+            }
+            tokio::time::sleep(delay).await;
+            delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+        }
+    }
+
+    async fn open(&self, args: &OpenConnectionArguments, secret: Option<&Secret>) -> Result<Connection, String> {
+        let mut args = args.clone();
+        if let Some(secret) = secret {
+            args.credentials(Self::sasl(&self.auth_mechanism, &self.username, &secret.token));
+        }
+        match self.attempt_timeout {
+            Some(timeout) => match tokio::time::timeout(timeout, Connection::open(&args)).await {
+                Ok(attempt) => attempt.map_err(|e| e.to_string()),
+                Err(_) => Err(format!("no answer within {timeout:?}")),
+            },
+            None => Connection::open(&args).await.map_err(|e| e.to_string()),
+        }
+    }
+
+    /// Sends each new token to `connection` with `connection.update-secret`
+    /// ahead of the current one's expiry, until the connection closes.
+    fn refresh_secret(&self, connection: Connection, mut current: Secret) {
+        let Some(provider) = self.credentials.clone() else {
+            return;
+        };
+        let task = tokio::spawn(async move {
+            while let Some(delay) = current.refresh_in {
+                tokio::time::sleep(delay.max(MIN_REFRESH_DELAY)).await;
+                if !connection.is_open() {
+                    return;
+                }
+                match provider.fetch().await {
+                    Ok(next) => {
+                        if next.token != current.token
+                            && connection.update_secret(&next.token, "token refresh").await.is_err()
+                        {
+                            return;
+                        }
+                        current = next;
+                    }
+                    // Retried until the token expires and the broker closes
+                    // the connection, which then reconnects with a new one.
+                    Err(_) => current.refresh_in = Some(MIN_REFRESH_DELAY),
+                }
+            }
+        });
+        if let Some(previous) = self.refresh.lock().unwrap().replace(task) {
+            previous.abort();
+        }
+    }
+
+    /// Endpoint indices in the order to try them. With `skip_current` the
+    /// node that just dropped goes last.
+    fn attempt_order(&self, skip_current: bool) -> Vec<usize> {
+        let count = self.endpoints.len();
+        let current = self.current.load(Ordering::Relaxed);
+        let mut order: Vec<usize> = match self.host_selection {
+            HostSelection::InOrder => (0..count).map(|i| (current + i) % count).collect(),
+            HostSelection::Random => {
+                let mut order: Vec<usize> = (0..count).collect();
+                shuffle(&mut order);
+                order
+            }
+        };
+        if skip_current && count > 1 {
+            order.retain(|&i| i != current);
+            order.push(current);
+        }
+        order
+    }
+
+    pub(crate) async fn open_channel(&self, timeout: Option<Duration>) -> Result<Channel, AppError> {
+        let connection = self.connection(timeout).await?;
+        let channel = connection
+            .open_channel(None)
+            .await
+            .map_err(|e| AppError::new(ErrorType::ConnectionClosed, e.to_string()))?;
+        exceptions::forget_close(&channel);
+        channel
+            .register_callback(CloseCallback)
+            .await
+            .map_err(|e| AppError::new(ErrorType::ConnectionClosed, e.to_string()))?;
+        Ok(channel)
+    }
+
+    pub(crate) fn is_disposed(&self) -> bool {
+        self.disposed.load(Ordering::Acquire)
+    }
+
+    pub(crate) async fn dispose(&self) {
+        self.disposed.store(true, Ordering::Release);
+        if let Some(refresh) = self.refresh.lock().unwrap().take() {
+            refresh.abort();
+        }
+        if let Some(connection) = self.connection.lock().await.take() {
+            let _ = connection.close().await;
+        }
+    }
+}
+
+/// Records why the broker closed a channel, so the command it refused raises
+/// the matching error.
+struct CloseCallback;
+
+#[async_trait]
+impl ChannelCallback for CloseCallback {
+    async fn close(&mut self, channel: &Channel, close: CloseChannel) -> Result<(), AmqprsError> {
+        exceptions::record_close(channel, &close);
+        Ok(())
+    }
+    async fn cancel(&mut self, _channel: &Channel, _cancel: Cancel) -> Result<(), AmqprsError> {
+        Ok(())
+    }
+    async fn flow(&mut self, _channel: &Channel, active: bool) -> Result<bool, AmqprsError> {
+        Ok(active)
+    }
+    async fn publish_ack(&mut self, _channel: &Channel, _ack: Ack) {}
+    async fn publish_nack(&mut self, _channel: &Channel, _nack: Nack) {}
+    async fn publish_return(
+        &mut self,
+        _channel: &Channel,
+        _ret: Return,
+        _basic_properties: BasicProperties,
+        _content: Vec<u8>,
+    ) {
+    }
+}
+
+/// Fisher-Yates shuffle seeded from the std hasher's random keys, which is
+/// plenty to spread clients over a cluster.
+fn shuffle(items: &mut [usize]) {
+    let mut state = RandomState::new().build_hasher().finish() | 1;
+    for i in (1..items.len()).rev() {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        items.swap(i, (state % (i as u64 + 1)) as usize);
+    }
+}
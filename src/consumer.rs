@@ -0,0 +1,711 @@
+use std::{
+    collections::BTreeSet,
+    fmt,
+    future::Future,
+    pin::Pin,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex, RwLock,
+    },
+    time::Duration,
+};
+
+use amqp_client_rust::amqprs::channel::{
+    BasicAckArguments, BasicCancelArguments, BasicConsumeArguments, BasicNackArguments, BasicQosArguments,
+    BasicRejectArguments, Channel, ConsumerMessage, QueueBindArguments, QueueDeclareArguments,
+};
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver},
+    task::JoinHandle,
+};
+
+use crate::{
+    connection::Connector,
+    exceptions::{channel_error, AppError, ErrorType},
+    message::Message,
+    publisher::Publisher,
+    rpc::RemoteError,
+    streaming::StreamControl,
+    topology::{self, ExchangeSpec, QueueOptions},
+    QoSConfig,
+};
+
+const RECONSUME_DELAY: Duration = Duration::from_secs(1);
+/// How long a delivery no route handles is held before it goes back to the
+/// queue, so the queue does not spin on it between consumers.
+const UNROUTED_REQUEUE_DELAY: Duration = Duration::from_secs(1);
+const REPLY_TIMEOUT: Duration = Duration::from_secs(16);
+
+pub(crate) type HandlerError = Box<dyn std::error::Error + Send + Sync>;
+/// Resolves to the reply of an RPC handler; subscribe handlers resolve to
+/// `None`.
+pub(crate) type HandlerFuture = Pin<Box<dyn Future<Output = Result<Option<Reply>, HandlerError>> + Send>>;
+
+/// What an RPC handler answers with.
+pub(crate) enum Reply {
+    Message(Box<Message>),
+    /// Chunks of a streamed reply. The stream ends when the sender is dropped
+    /// or after an error.
+    Stream(mpsc::Receiver<Result<Message, HandlerError>>),
+}
+pub(crate) type Handler = Arc<dyn Fn(Message) -> HandlerFuture + Send + Sync>;
+
+/// Delivery tags of one consumer channel that still await an ack, nack or
+/// reject. Tags are only valid on the channel that delivered them, so a
+/// reconnect makes every outstanding `Acker` stale.
+pub(crate) struct PendingAcks {
+    channel: Channel,
+    tags: Mutex<BTreeSet<u64>>,
+}
+
+impl PendingAcks {
+    fn new(channel: Channel) -> Arc<Self> {
+        Arc::new(Self {
+            channel,
+            tags: Mutex::new(BTreeSet::new()),
+        })
+    }
+
+    fn track(self: &Arc<Self>, delivery_tag: u64) -> Acker {
+        self.tags.lock().unwrap().insert(delivery_tag);
+        Acker {
+            pending: Arc::clone(self),
+            delivery_tag,
+        }
+    }
+
+    /// Removes `delivery_tag` (and every lower tag when `multiple`) from the
+    /// pending set, failing if it was already settled.
+    fn settle(&self, delivery_tag: u64, multiple: bool) -> Result<(), AppError> {
+        if !self.channel.is_open() {
+            return Err(AppError::new(
+                ErrorType::StaleDelivery,
+                "the channel that delivered this message has been closed or reconnected",
+            ));
+        }
+        let mut tags = self.tags.lock().unwrap();
+        if !tags.remove(&delivery_tag) {
+            return Err(AppError::new(
+                ErrorType::AlreadySettled,
+                format!("delivery tag {delivery_tag} was already settled"),
+            ));
+        }
+        if multiple {
+            let rest = tags.split_off(&delivery_tag);
+            *tags = rest;
+        }
+        Ok(())
+    }
+}
+
+/// Settles a single delivery. Held by the `Message` handed to the handler.
+#[derive(Clone)]
+pub(crate) struct Acker {
+    pending: Arc<PendingAcks>,
+    delivery_tag: u64,
+}
+
+impl fmt::Debug for Acker {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Acker").field("delivery_tag", &self.delivery_tag).finish()
+    }
+}
+
+impl Acker {
+    pub(crate) async fn ack(&self, multiple: bool) -> Result<(), AppError> {
+        self.pending.settle(self.delivery_tag, multiple)?;
+        self.pending
+            .channel
+            .basic_ack(BasicAckArguments::new(self.delivery_tag, multiple))
+            .await
+            .map_err(|e| AppError::new(ErrorType::ConnectionClosed, e.to_string()))
+    }
+
+    pub(crate) async fn nack(&self, requeue: bool, multiple: bool) -> Result<(), AppError> {
+        self.pending.settle(self.delivery_tag, multiple)?;
+        self.pending
+            .channel
+            .basic_nack(BasicNackArguments::new(self.delivery_tag, multiple, requeue))
+            .await
+            .map_err(|e| AppError::new(ErrorType::ConnectionClosed, e.to_string()))
+    }
+
+    pub(crate) async fn reject(&self, requeue: bool) -> Result<(), AppError> {
+        self.pending.settle(self.delivery_tag, false)?;
+        self.pending
+            .channel
+            .basic_reject(BasicRejectArguments::new(self.delivery_tag, requeue))
+            .await
+            .map_err(|e| AppError::new(ErrorType::ConnectionClosed, e.to_string()))
+    }
+}
+
+/// What a subscription consumes and how.
+#[derive(Clone)]
+pub(crate) struct SubscribeSpec {
+    pub exchange: ExchangeSpec,
+    pub routing_key: String,
+    pub queue_options: QueueOptions,
+    /// Overrides the prefetch of the `QoSConfig`.
+    pub prefetch: Option<u16>,
+    /// Leaves settling to whoever holds the message instead of acking or
+    /// nacking once the handler returns.
+    pub manual_settle: bool,
+    /// Sends the handler's traceback along with RPC error replies.
+    pub include_traceback: bool,
+}
+
+impl SubscribeSpec {
+    /// Whether a delivery published to `exchange_name` with `routing_key` is
+    /// one this subscription's binding routed to the shared queue. The same
+    /// key may be bound on several exchanges, each by its own subscription.
+    fn matches(&self, exchange_name: &str, routing_key: &str) -> bool {
+        if self.exchange.name != exchange_name {
+            return false;
+        }
+        // Fanout and headers deliveries carry whatever routing key they were
+        // published with.
+        self.exchange.ignores_routing_key() || matches_routing_key(&self.routing_key, routing_key)
+    }
+}
+
+#[derive(Default)]
+struct ConsumerSlot {
+    channel: Option<Channel>,
+    consumer_tag: Option<String>,
+}
+
+/// One `subscribe`/`provide_resource` call, shared by its consume task, its
+/// route and the `Subscription` handle given to Python.
+pub(crate) struct SubscriptionState {
+    spec: SubscribeSpec,
+    process_timeout: Option<Duration>,
+    consumer: Mutex<ConsumerSlot>,
+    task: Mutex<Option<JoinHandle<()>>>,
+    /// Bumped by every start and pause, so a consume task whose consumer was
+    /// cancelled on purpose does not consume again once its buffer drained.
+    generation: AtomicU64,
+    paused: AtomicBool,
+    cancelled: AtomicBool,
+    pub(crate) delivered: AtomicU64,
+    pub(crate) handled: AtomicU64,
+    pub(crate) failed: AtomicU64,
+}
+
+impl SubscriptionState {
+    pub(crate) fn consumer_tag(&self) -> Option<String> {
+        self.consumer.lock().unwrap().consumer_tag.clone()
+    }
+
+    pub(crate) fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+
+    fn abort(&self) {
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+    }
+}
+
+struct Route {
+    handler: Handler,
+    state: Arc<SubscriptionState>,
+}
+
+/// Answers RPC requests on the `reply_to` they name.
+struct Replier {
+    publisher: Publisher,
+    streams: StreamControl,
+}
+
+impl Replier {
+    /// Sends the handler's reply, each chunk of a streamed reply, or an error
+    /// reply, so a failed handler does not leave the caller waiting for its
+    /// response timeout.
+    async fn reply(
+        &self,
+        reply_to: &str,
+        correlation_id: Option<String>,
+        outcome: Result<Option<Reply>, HandlerError>,
+        include_traceback: bool,
+    ) -> Result<(), HandlerError> {
+        let (reply, result) = match outcome {
+            Ok(Some(Reply::Stream(chunks))) => {
+                return self.stream(reply_to, correlation_id, chunks, include_traceback).await;
+            }
+            Ok(Some(Reply::Message(mut reply))) => {
+                RemoteError::strip_headers(&mut reply);
+                (*reply, Ok(()))
+            }
+            Ok(None) => (Message::from_body(Vec::new().into(), None), Ok(())),
+            Err(e) => (RemoteError::from_handler_error(&e).to_reply(include_traceback), Err(e)),
+        };
+        let _ = self.send(reply_to, correlation_id, reply).await;
+        result
+    }
+
+    /// Sends every chunk as it is produced, then an end marker. A cancel from
+    /// the caller drops the chunks, which closes the handler's generator.
+    async fn stream(
+        &self,
+        reply_to: &str,
+        correlation_id: Option<String>,
+        mut chunks: mpsc::Receiver<Result<Message, HandlerError>>,
+        include_traceback: bool,
+    ) -> Result<(), HandlerError> {
+        let stream = self.streams.register().await;
+        let mut seq = 0;
+        loop {
+            let chunk = tokio::select! {
+                chunk = chunks.recv() => chunk,
+                _ = stream.cancelled() => return Ok(()),
+            };
+            let (message, result) = match chunk {
+                Some(Ok(mut message)) => {
+                    RemoteError::strip_headers(&mut message);
+                    (stream.chunk(message, seq), None)
+                }
+                Some(Err(e)) => (
+                    stream.end(RemoteError::from_handler_error(&e).to_reply(include_traceback), seq),
+                    Some(Err(e)),
+                ),
+                None => (stream.end(Message::from_body(Vec::new().into(), None), seq), Some(Ok(()))),
+            };
+            self.send(reply_to, correlation_id.clone(), message)
+                .await
+                .map_err(|e| e.to_string())?;
+            if let Some(result) = result {
+                return result;
+            }
+            seq += 1;
+        }
+    }
+
+    async fn send(&self, reply_to: &str, correlation_id: Option<String>, mut message: Message) -> Result<(), AppError> {
+        message.correlation_id = correlation_id;
+        self.publisher
+            .publish("", reply_to, message.wire_body()?, message.properties(), Some(REPLY_TIMEOUT))
+            .await
+    }
+}
+
+/// Consumers of one queue: the subscribe queue, or the RPC queue when built
+/// with `rpc_server`. Each subscription owns a consumer, but every consumer
+/// dispatches through the shared routing table, so a delivery reaches the
+/// handler whose binding key matches no matter which consumer the broker
+/// handed it to.
+pub(crate) struct Subscriber {
+    connector: Arc<Connector>,
+    queue_name: String,
+    auto_ack: bool,
+    prefetch: Option<u16>,
+    /// Publishes handler results to the `reply_to` of RPC requests.
+    replier: Option<Replier>,
+    routes: RwLock<Vec<Route>>,
+}
+
+impl Subscriber {
+    pub(crate) fn new(connector: Arc<Connector>, queue_name: String, qos: &QoSConfig) -> Self {
+        Self {
+            connector,
+            queue_name,
+            auto_ack: qos.sub_auto_ack,
+            prefetch: qos.sub_prefetch,
+            replier: None,
+            routes: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn rpc_server(connector: Arc<Connector>, queue_name: String, qos: &QoSConfig) -> Self {
+        Self {
+            replier: Some(Replier {
+                publisher: Publisher::new(Arc::clone(&connector), qos.rpc_server_confirm),
+                streams: StreamControl::new(Arc::clone(&connector)),
+            }),
+            connector,
+            queue_name,
+            auto_ack: qos.rpc_server_auto_ack,
+            prefetch: qos.rpc_server_prefetch,
+            routes: RwLock::new(Vec::new()),
+        }
+    }
+
+    pub(crate) fn queue_name(&self) -> &str {
+        &self.queue_name
+    }
+
+    pub(crate) async fn subscribe(
+        self: &Arc<Self>,
+        spec: SubscribeSpec,
+        handler: Handler,
+        process_timeout: Option<Duration>,
+        command_timeout: Option<Duration>,
+    ) -> Result<Arc<SubscriptionState>, AppError> {
+        let state = Arc::new(SubscriptionState {
+            spec,
+            process_timeout,
+            consumer: Mutex::new(ConsumerSlot::default()),
+            task: Mutex::new(None),
+            generation: AtomicU64::new(0),
+            paused: AtomicBool::new(false),
+            cancelled: AtomicBool::new(false),
+            delivered: AtomicU64::new(0),
+            handled: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+        });
+        {
+            let mut routes = self.routes.write().unwrap();
+            // Every subscription declares the shared queue, so options other
+            // than those of the live ones would be ignored or refused.
+            if routes.iter().any(|route| route.state.spec.queue_options != state.spec.queue_options) {
+                return Err(AppError::new(
+                    ErrorType::PreconditionFailed,
+                    format!(
+                        "queue '{}' is already consumed with other queue options than {:?}",
+                        self.queue_name, state.spec.queue_options
+                    ),
+                ));
+            }
+            // The route goes in first so deliveries racing the consume-ok are
+            // not rejected for lack of a handler.
+            routes.push(Route {
+                handler,
+                state: Arc::clone(&state),
+            });
+        }
+        if let Err(e) = self.start(&state, None, command_timeout).await {
+            self.remove_route(&state);
+            return Err(e);
+        }
+        Ok(state)
+    }
+
+    /// Stops the broker from delivering to the subscription. Deliveries other
+    /// consumers of the queue receive for it meanwhile are requeued, and so
+    /// are those already buffered for this consumer: its task keeps running
+    /// until basic.cancel closes the buffer, requeueing what is left in it.
+    pub(crate) async fn pause(&self, state: &SubscriptionState) -> Result<(), AppError> {
+        if state.is_cancelled() || state.paused.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        state.generation.fetch_add(1, Ordering::SeqCst);
+        let (channel, consumer_tag) = {
+            let mut slot = state.consumer.lock().unwrap();
+            (slot.channel.clone(), slot.consumer_tag.take())
+        };
+        if let (Some(channel), Some(consumer_tag)) = (channel, consumer_tag)
+            && channel.is_open()
+        {
+            channel
+                .basic_cancel(BasicCancelArguments::new(&consumer_tag))
+                .await
+                .map_err(channel_error(&channel))?;
+        }
+        Ok(())
+    }
+
+    pub(crate) async fn resume(
+        self: &Arc<Self>,
+        state: &Arc<SubscriptionState>,
+        timeout: Option<Duration>,
+    ) -> Result<(), AppError> {
+        if state.is_cancelled() {
+            return Err(AppError::new(
+                ErrorType::UnexpectedResult,
+                "a cancelled subscription cannot be resumed",
+            ));
+        }
+        if !state.paused.swap(false, Ordering::SeqCst) {
+            return Ok(());
+        }
+        // Resuming on the same channel keeps the deliveries that were still
+        // being handled when it paused settleable.
+        let channel = state.consumer.lock().unwrap().channel.clone();
+        if let Err(e) = self.start(state, channel, timeout).await {
+            state.paused.store(true, Ordering::SeqCst);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Cancels the consumer and closes its channel; unsettled deliveries go
+    /// back to the queue. The route is removed last, so the consumer stops
+    /// before its key becomes unhandled.
+    pub(crate) async fn cancel(&self, state: &SubscriptionState) -> Result<(), AppError> {
+        if state.cancelled.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        state.abort();
+        let slot = std::mem::take(&mut *state.consumer.lock().unwrap());
+        let result = Self::close_consumer(slot).await;
+        self.remove_route(state);
+        result
+    }
+
+    async fn close_consumer(slot: ConsumerSlot) -> Result<(), AppError> {
+        let Some(channel) = slot.channel.filter(Channel::is_open) else {
+            return Ok(());
+        };
+        if let Some(consumer_tag) = slot.consumer_tag {
+            channel
+                .basic_cancel(BasicCancelArguments::new(&consumer_tag))
+                .await
+                .map_err(channel_error(&channel))?;
+        }
+        channel.close().await.map_err(AppError::from)
+    }
+
+    pub(crate) fn dispose(&self) {
+        for route in self.routes.read().unwrap().iter() {
+            route.state.abort();
+        }
+    }
+
+    fn remove_route(&self, state: &SubscriptionState) {
+        self.routes
+            .write()
+            .unwrap()
+            .retain(|route| !std::ptr::eq(Arc::as_ptr(&route.state), state));
+    }
+
+    /// Starts the consumer of `state` on `channel` if it is still open, or on
+    /// a new channel otherwise.
+    async fn start(
+        self: &Arc<Self>,
+        state: &Arc<SubscriptionState>,
+        channel: Option<Channel>,
+        timeout: Option<Duration>,
+    ) -> Result<(), AppError> {
+        let channel = match channel.filter(Channel::is_open) {
+            Some(channel) => channel,
+            None => self.connector.open_channel(timeout).await?,
+        };
+        let (consumer_tag, rx) = self.consume(&channel, &state.spec).await?;
+        let generation = state.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        if !self.attach(state, &channel, consumer_tag).await {
+            return Ok(());
+        }
+        let this = Arc::clone(self);
+        let task_state = Arc::clone(state);
+        let task = tokio::spawn(async move { this.run(task_state, generation, channel, rx).await });
+        *state.task.lock().unwrap() = Some(task);
+        Ok(())
+    }
+
+    /// Records the consumer that just started for `state`, or cancels it again
+    /// if the subscription was paused or cancelled meanwhile.
+    async fn attach(&self, state: &SubscriptionState, channel: &Channel, consumer_tag: String) -> bool {
+        {
+            let mut slot = state.consumer.lock().unwrap();
+            if !state.is_paused() && !state.is_cancelled() {
+                slot.channel = Some(channel.clone());
+                slot.consumer_tag = Some(consumer_tag);
+                return true;
+            }
+        }
+        let _ = channel.basic_cancel(BasicCancelArguments::new(&consumer_tag)).await;
+        false
+    }
+
+    async fn consume(
+        &self,
+        channel: &Channel,
+        spec: &SubscribeSpec,
+    ) -> Result<(String, UnboundedReceiver<ConsumerMessage>), AppError> {
+        topology::declare_exchange(channel, &spec.exchange).await?;
+        let queue = spec.queue_options.to_spec(&self.queue_name);
+        channel
+            .queue_declare(
+                QueueDeclareArguments::new(&queue.name)
+                    .durable(queue.durable)
+                    .exclusive(queue.exclusive)
+                    .auto_delete(queue.auto_delete)
+                    .arguments(queue.arguments)
+                    .finish(),
+            )
+            .await
+            .map_err(channel_error(channel))?;
+        channel
+            .queue_bind(QueueBindArguments::new(
+                &self.queue_name,
+                &spec.exchange.name,
+                &spec.routing_key,
+            ))
+            .await
+            .map_err(channel_error(channel))?;
+        if let Some(prefetch) = spec.prefetch.or(self.prefetch) {
+            channel
+                .basic_qos(BasicQosArguments::new(0, prefetch, false))
+                .await
+                .map_err(channel_error(channel))?;
+        }
+        channel
+            .basic_consume_rx(
+                BasicConsumeArguments::new(&self.queue_name, "")
+                    .manual_ack(!self.auto_ack)
+                    .finish(),
+            )
+            .await
+            .map_err(channel_error(channel))
+    }
+
+    async fn run(
+        self: Arc<Self>,
+        state: Arc<SubscriptionState>,
+        generation: u64,
+        mut channel: Channel,
+        mut rx: UnboundedReceiver<ConsumerMessage>,
+    ) {
+        loop {
+            let pending = PendingAcks::new(channel.clone());
+            while let Some(delivery) = rx.recv().await {
+                let this = Arc::clone(&self);
+                let pending = Arc::clone(&pending);
+                let process_timeout = state.process_timeout;
+                tokio::spawn(async move { this.dispatch(pending, delivery, process_timeout).await });
+            }
+            // The channel or the connection went away: consume again once the
+            // connector has reconnected.
+            loop {
+                if self.connector.is_disposed()
+                    || state.is_paused()
+                    || state.is_cancelled()
+                    || state.generation.load(Ordering::SeqCst) != generation
+                {
+                    return;
+                }
+                let consumer = match self.connector.open_channel(None).await {
+                    Ok(new_channel) => self
+                        .consume(&new_channel, &state.spec)
+                        .await
+                        .map(|consumer| (new_channel, consumer)),
+                    Err(e) => Err(e),
+                };
+                match consumer {
+                    Ok((new_channel, (consumer_tag, new_rx))) => {
+                        if !self.attach(&state, &new_channel, consumer_tag).await {
+                            return;
+                        }
+                        channel = new_channel;
+                        rx = new_rx;
+                        break;
+                    }
+                    Err(_) => tokio::time::sleep(RECONSUME_DELAY).await,
+                }
+            }
+        }
+    }
+
+    async fn dispatch(
+        &self,
+        pending: Arc<PendingAcks>,
+        delivery: ConsumerMessage,
+        process_timeout: Option<Duration>,
+    ) {
+        let (Some(deliver), Some(properties), Some(content)) =
+            (delivery.deliver, delivery.basic_properties, delivery.content)
+        else {
+            return;
+        };
+        // With auto ack the broker settled the delivery already.
+        let acker = (!self.auto_ack).then(|| pending.track(deliver.delivery_tag()));
+        let route = self
+            .routes
+            .read()
+            .unwrap()
+            .iter()
+            .find(|route| route.state.spec.matches(deliver.exchange(), deliver.routing_key()))
+            .map(|route| (Arc::clone(&route.handler), Arc::clone(&route.state)));
+        if let Some((_, state)) = &route
+            && state.is_paused()
+        {
+            if let Some(acker) = acker {
+                let _ = acker.nack(true, false).await;
+            }
+            return;
+        }
+        // Nothing here handles the key, e.g. right after `Subscription.cancel`
+        // while the binding stays for other processes sharing the queue, or
+        // while a subscription is being registered. The delivery is never
+        // dropped for it: it goes back to the queue for whoever handles it.
+        if route.is_none()
+            && let Some(acker) = acker
+        {
+            tokio::spawn(async move {
+                tokio::time::sleep(UNROUTED_REQUEUE_DELAY).await;
+                let _ = acker.nack(true, false).await;
+            });
+            return;
+        }
+        let reply_to = properties.reply_to().cloned();
+        let correlation_id = properties.correlation_id().cloned();
+        let mut handled = None;
+        let outcome: Result<Option<Reply>, HandlerError> = match &route {
+            Some((handler, state)) => match Message::from_delivery(&deliver, &properties, content) {
+                Ok(mut message) => {
+                    message.acker = acker.clone();
+                    state.delivered.fetch_add(1, Ordering::Relaxed);
+                    handled = Some(state);
+                    match process_timeout {
+                        Some(timeout) => tokio::time::timeout(timeout, handler(message))
+                            .await
+                            .unwrap_or_else(|e| Err(e.into())),
+                        None => handler(message).await,
+                    }
+                }
+                Err(e) => Err(e.into()),
+            },
+            None => Err(format!("no handler for routing key '{}'", deliver.routing_key()).into()),
+        };
+        // A streamed reply only succeeded once its last chunk went out.
+        let outcome = match (&self.replier, reply_to) {
+            (Some(replier), Some(reply_to)) => {
+                let include_traceback = route.as_ref().is_some_and(|(_, state)| state.spec.include_traceback);
+                replier.reply(&reply_to, correlation_id, outcome, include_traceback).await
+            }
+            _ => outcome.map(|_| ()),
+        };
+        if let Some(state) = handled {
+            let counter = if outcome.is_ok() { &state.handled } else { &state.failed };
+            counter.fetch_add(1, Ordering::Relaxed);
+        }
+        // Settle on the handler's behalf unless it already did so through
+        // `Message.ack`/`nack`/`reject`; `AlreadySettled` is expected then.
+        let Some(acker) = acker else {
+            return;
+        };
+        let manual_settle = route.is_some_and(|(_, state)| state.spec.manual_settle);
+        let _ = match outcome {
+            Ok(_) if manual_settle => return,
+            // The message never reached its reader, so it goes back.
+            Err(_) if manual_settle => acker.nack(true, false).await,
+            Ok(_) => acker.ack(false).await,
+            Err(_) => acker.nack(false, false).await,
+        };
+    }
+}
+
+/// Matches a routing key against a binding key using topic exchange rules:
+/// `*` stands for exactly one word and `#` for zero or more words.
+fn matches_routing_key(binding_key: &str, routing_key: &str) -> bool {
+    fn matches(pattern: &[&str], key: &[&str]) -> bool {
+        match (pattern.first(), key.first()) {
+            (None, None) => true,
+            (Some(&"#"), _) => matches(&pattern[1..], key) || (!key.is_empty() && matches(pattern, &key[1..])),
+            (Some(&"*"), Some(_)) => matches(&pattern[1..], &key[1..]),
+            (Some(p), Some(k)) if p == k => matches(&pattern[1..], &key[1..]),
+            _ => false,
+        }
+    }
+    if binding_key == routing_key {
+        return true;
+    }
+    let pattern: Vec<&str> = binding_key.split('.').collect();
+    let key: Vec<&str> = routing_key.split('.').collect();
+    matches(&pattern, &key)
+}
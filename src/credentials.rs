@@ -0,0 +1,159 @@
+use std::{
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use pyo3::{
+    intern,
+    prelude::*,
+    types::{PyBool, PyDict},
+};
+use pyo3_async_runtimes::TaskLocals;
+
+use crate::exceptions::{AppError, ErrorType};
+
+/// How often a token file is checked for a new token when the token itself
+/// does not say when it expires.
+pub(crate) const DEFAULT_POLL_INTERVAL: u64 = 30;
+/// Seconds before a JWT's `exp` that the next token is fetched.
+pub(crate) const DEFAULT_REFRESH_BEFORE: u64 = 60;
+
+enum Source {
+    /// Called with no arguments; returns the token or an awaitable of it.
+    Callable(Py<PyAny>),
+    File { path: PathBuf, poll_interval: Duration },
+}
+
+struct ProviderInner {
+    source: Source,
+    refresh_before: Duration,
+    /// Event loop of the first fetch from an `AsyncEventbus` call, reused by
+    /// fetches from background reconnects and refreshes.
+    locals: Mutex<Option<TaskLocals>>,
+}
+
+/// A token used as the password, fetched on every connect and reconnect and
+/// pushed to the open connection with `connection.update-secret` before it
+/// expires, as RabbitMQ's OAuth 2.0 backend expects.
+#[pyclass(from_py_object)]
+#[derive(Clone)]
+pub struct CredentialsProvider {
+    inner: Arc<ProviderInner>,
+}
+
+/// A fetched token and when to fetch the next one.
+pub(crate) struct Secret {
+    pub token: String,
+    pub refresh_in: Option<Duration>,
+}
+
+impl CredentialsProvider {
+    fn new(source: Source, refresh_before: u64) -> Self {
+        Self {
+            inner: Arc::new(ProviderInner {
+                source,
+                refresh_before: Duration::from_secs(refresh_before),
+                locals: Mutex::new(None),
+            }),
+        }
+    }
+
+    pub(crate) async fn fetch(&self) -> Result<Secret, AppError> {
+        let token = match &self.inner.source {
+            Source::Callable(callable) => self.call(callable).await.map_err(|e| {
+                AppError::new(ErrorType::AccessRefused, format!("the credentials provider failed: {e}"))
+            })?,
+            Source::File { path, .. } => std::fs::read_to_string(path)
+                .map_err(|e| {
+                    AppError::new(
+                        ErrorType::AccessRefused,
+                        format!("could not read the token file {}: {e}", path.display()),
+                    )
+                })?
+                .trim()
+                .to_owned(),
+        };
+        let until_expiry = Python::attach(|py| jwt_expiry(py, &token)).map(|expiry| {
+            expiry
+                .duration_since(SystemTime::now())
+                .unwrap_or_default()
+                .saturating_sub(self.inner.refresh_before)
+        });
+        let refresh_in = match &self.inner.source {
+            Source::File { poll_interval, .. } => Some(until_expiry.map_or(*poll_interval, |d| d.min(*poll_interval))),
+            Source::Callable(_) => until_expiry,
+        };
+        Ok(Secret { token, refresh_in })
+    }
+
+    async fn call(&self, callable: &Py<PyAny>) -> PyResult<String> {
+        let pending = Python::attach(|py| -> PyResult<Result<String, _>> {
+            let result = callable.bind(py).call0()?;
+            if !result.hasattr(intern!(py, "__await__"))? {
+                return Ok(Ok(result.extract()?));
+            }
+            let locals = self.locals(py)?;
+            Ok(Err(pyo3_async_runtimes::into_future_with_locals(&locals, result)?))
+        })?;
+        match pending {
+            Ok(token) => Ok(token),
+            Err(future) => {
+                let token = future.await?;
+                Python::attach(|py| token.extract(py))
+            }
+        }
+    }
+
+    fn locals(&self, py: Python<'_>) -> PyResult<TaskLocals> {
+        let mut stored = self.inner.locals.lock().unwrap();
+        if let Ok(locals) = pyo3_async_runtimes::tokio::get_current_locals(py) {
+            return Ok(stored.insert(locals).clone());
+        }
+        stored.clone().ok_or_else(|| {
+            pyo3::exceptions::PyRuntimeError::new_err(
+                "an async credentials provider needs an event loop, use it with AsyncEventbus",
+            )
+        })
+    }
+}
+
+#[pymethods]
+impl CredentialsProvider {
+    /// `provider` returns the token, or is a coroutine function resolving to
+    /// it. A JWT is refreshed `refresh_before` seconds before its `exp`.
+    #[staticmethod]
+    #[pyo3(signature = (provider, refresh_before=DEFAULT_REFRESH_BEFORE))]
+    fn from_callable(provider: Py<PyAny>, refresh_before: u64) -> Self {
+        Self::new(Source::Callable(provider), refresh_before)
+    }
+
+    /// Reads the token from `path`, such as a projected service account
+    /// token, and re-reads it every `poll_interval` seconds.
+    #[staticmethod]
+    #[pyo3(signature = (path, poll_interval=DEFAULT_POLL_INTERVAL, refresh_before=DEFAULT_REFRESH_BEFORE))]
+    pub(crate) fn from_file(path: PathBuf, poll_interval: u64, refresh_before: u64) -> Self {
+        let poll_interval = Duration::from_secs(poll_interval.max(1));
+        Self::new(Source::File { path, poll_interval }, refresh_before)
+    }
+}
+
+/// The top-level numeric `exp` claim of a JWT, `None` for tokens that are
+/// not JWTs or have none.
+fn jwt_expiry(py: Python<'_>, token: &str) -> Option<SystemTime> {
+    let segment = token.split('.').nth(1)?;
+    let padding = "=".repeat((4 - segment.len() % 4) % 4);
+    let payload = py
+        .import(intern!(py, "base64"))
+        .and_then(|base64| base64.call_method1(intern!(py, "urlsafe_b64decode"), (format!("{segment}{padding}"),)))
+        .and_then(|payload| py.import(intern!(py, "json"))?.call_method1(intern!(py, "loads"), (payload,)))
+        .ok()?;
+    let exp = payload.cast::<PyDict>().ok()?.get_item(intern!(py, "exp")).ok()??;
+    // `true` would extract as 1.
+    if exp.is_instance_of::<PyBool>() {
+        return None;
+    }
+    let seconds = exp.extract::<f64>().ok()?;
+    let seconds = Duration::try_from_secs_f64(seconds).ok()?;
+    Some(UNIX_EPOCH + seconds)
+}
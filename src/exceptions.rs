@@ -1,15 +1,163 @@
-use std::fmt::{self, Display};
-use pyo3::{PyErr};
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    sync::{LazyLock, Mutex},
+};
+use pyo3::{create_exception, prelude::*, PyErr};
 use pyo3::exceptions::{PyException};
-use amqp_client_rust::errors::{AppError as RuAppError, AppErrorType};
+use amqp_client_rust::{
+    amqprs::{channel::Channel, error::Error as AmqprsError, CloseChannel},
+    errors::{AppError as RuAppError, AppErrorType},
+};
 
+create_exception!(amqp_rs, AmqpError, PyException, "Base class for every error raised by amqp_rs.");
+create_exception!(amqp_rs, NackError, AmqpError, "The broker answered a confirmed publish with a nack.");
+create_exception!(amqp_rs, PublishTimeoutError, AmqpError, "No publisher confirmation arrived within the publish timeout.");
+create_exception!(amqp_rs, ResponseTimeoutError, AmqpError, "No RPC response arrived within the response timeout.");
+create_exception!(amqp_rs, AutoReconnectError, AmqpError, "The connection could not be re-established within the connection timeout.");
+create_exception!(amqp_rs, ConnectionClosedError, AmqpError, "The connection or channel was closed while the operation was in progress.");
+create_exception!(amqp_rs, UnexpectedResultError, AmqpError, "The broker or the client returned a result that could not be handled.");
+create_exception!(amqp_rs, InternalError, AmqpError, "The client failed internally, e.g. a body could not be compressed.");
+create_exception!(amqp_rs, OperationTimeoutError, AmqpError, "A broker operation did not complete within its timeout.");
+create_exception!(amqp_rs, UnsupportedContentTypeError, AmqpError, "The message's content type or content encoding is not supported.");
+create_exception!(amqp_rs, AlreadySettledError, AmqpError, "The message was already acknowledged, rejected or auto-acknowledged.");
+create_exception!(amqp_rs, NotFoundError, AmqpError, "The broker refused the command because an exchange or queue does not exist (404).");
+create_exception!(amqp_rs, PreconditionFailedError, AmqpError, "The broker refused the command because it conflicts with an existing declaration (406).");
+create_exception!(amqp_rs, AccessRefusedError, AmqpError, "The broker refused the command for lack of permissions (403).");
+create_exception!(amqp_rs, RemoteHandlerError, AmqpError, "The RPC provider's handler raised; exception_type, message and traceback describe the remote exception.");
+create_exception!(amqp_rs, StaleDeliveryError, AmqpError, "The channel that delivered the message was closed or reconnected, so it can no longer be settled.");
+
+/// Python-facing classification of an error, one variant per exception class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorType {
+    Nack,
+    PublishTimeout,
+    ResponseTimeout,
+    Timeout,
+    AutoReconnect,
+    ConnectionClosed,
+    Internal,
+    UnexpectedResult,
+    UnsupportedContentType,
+    AlreadySettled,
+    StaleDelivery,
+    NotFound,
+    PreconditionFailed,
+    AccessRefused,
+}
+
+impl ErrorType {
+    /// The `error_type` attribute of the raised exception: the inner crate's
+    /// `AppErrorType` variant the error came from, or this variant's name for
+    /// errors only amqp_rs raises.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorType::Nack => "NackError",
+            ErrorType::PublishTimeout => "PublishTimeout",
+            ErrorType::ResponseTimeout => "RpcTimeout",
+            ErrorType::Timeout => "TimeoutError",
+            ErrorType::AutoReconnect => "AutoReconnect",
+            ErrorType::ConnectionClosed => "ConnectionClosed",
+            ErrorType::Internal => "InternalError",
+            ErrorType::UnexpectedResult => "UnexpectedResultError",
+            ErrorType::UnsupportedContentType => "UnsupportedContentType",
+            ErrorType::AlreadySettled => "AlreadySettled",
+            ErrorType::StaleDelivery => "StaleDelivery",
+            ErrorType::NotFound => "NotFound",
+            ErrorType::PreconditionFailed => "PreconditionFailed",
+            ErrorType::AccessRefused => "AccessRefused",
+        }
+    }
+
+    fn new_err(&self, msg: String) -> PyErr {
+        match self {
+            ErrorType::Nack => NackError::new_err(msg),
+            ErrorType::PublishTimeout => PublishTimeoutError::new_err(msg),
+            ErrorType::ResponseTimeout => ResponseTimeoutError::new_err(msg),
+            ErrorType::Timeout => OperationTimeoutError::new_err(msg),
+            ErrorType::AutoReconnect => AutoReconnectError::new_err(msg),
+            ErrorType::ConnectionClosed => ConnectionClosedError::new_err(msg),
+            ErrorType::Internal => InternalError::new_err(msg),
+            ErrorType::UnexpectedResult => UnexpectedResultError::new_err(msg),
+            ErrorType::UnsupportedContentType => UnsupportedContentTypeError::new_err(msg),
+            ErrorType::AlreadySettled => AlreadySettledError::new_err(msg),
+            ErrorType::StaleDelivery => StaleDeliveryError::new_err(msg),
+            ErrorType::NotFound => NotFoundError::new_err(msg),
+            ErrorType::PreconditionFailed => PreconditionFailedError::new_err(msg),
+            ErrorType::AccessRefused => AccessRefusedError::new_err(msg),
+        }
+    }
+}
+
+impl From<&AppErrorType> for ErrorType {
+    fn from(error_type: &AppErrorType) -> Self {
+        match error_type {
+            AppErrorType::NackError => ErrorType::Nack,
+            AppErrorType::RpcTimeout => ErrorType::ResponseTimeout,
+            AppErrorType::TimeoutError => ErrorType::Timeout,
+            AppErrorType::InternalError => ErrorType::Internal,
+            AppErrorType::UnexpectedResultError => ErrorType::UnexpectedResult,
+            AppErrorType::UnsupportedContentType => ErrorType::UnsupportedContentType,
+        }
+    }
+}
+
+/// Reply code and text of a `channel.close` the broker sent.
+type CloseReason = (u16, String);
+
+/// Close reasons by connection name and channel id. A command pending on the
+/// channel only sees its responder dropped, so this is where the reason
+/// survives.
+static CLOSED_CHANNELS: LazyLock<Mutex<HashMap<(String, u16), CloseReason>>> =
+    LazyLock::new(Default::default);
+
+fn channel_key(channel: &Channel) -> (String, u16) {
+    (channel.connection_name().to_owned(), channel.channel_id())
+}
+
+/// Records why the broker closed `channel`; called from its close callback.
+pub(crate) fn record_close(channel: &Channel, close: &CloseChannel) {
+    CLOSED_CHANNELS.lock().unwrap().insert(
+        channel_key(channel),
+        (close.reply_code(), close.reply_text().to_owned()),
+    );
+}
+
+/// Forgets the close reason of an earlier channel that had the same id.
+pub(crate) fn forget_close(channel: &Channel) {
+    CLOSED_CHANNELS.lock().unwrap().remove(&channel_key(channel));
+}
+
+/// Classifies an error of a command sent on `channel` by the reply code the
+/// broker closed the channel with, if it did.
+pub(crate) fn channel_error(channel: &Channel) -> impl FnOnce(AmqprsError) -> AppError + '_ {
+    move |error| {
+        let closed = CLOSED_CHANNELS.lock().unwrap().remove(&channel_key(channel));
+        let Some((reply_code, reply_text)) = closed else {
+            return AppError::from(error);
+        };
+        let error_type = match reply_code {
+            404 => ErrorType::NotFound,
+            406 => ErrorType::PreconditionFailed,
+            403 => ErrorType::AccessRefused,
+            _ => ErrorType::UnexpectedResult,
+        };
+        AppError::new(error_type, reply_text)
+    }
+}
+
+impl From<AmqprsError> for AppError {
+    fn from(error: AmqprsError) -> Self {
+        AppError::new(ErrorType::UnexpectedResult, error.to_string())
+    }
+}
 
 impl From<RuAppError> for AppError {
     fn from(error: RuAppError) -> Self {
         Self {
+            error_type: ErrorType::from(&error.error_type),
             message: error.message,
             description: error.description,
-            error_type: error.error_type,
         }
     }
 }
@@ -18,7 +166,17 @@ impl From<RuAppError> for AppError {
 pub struct AppError {
     pub message: Option<String>,
     pub description: Option<String>,
-    pub error_type: AppErrorType,
+    pub error_type: ErrorType,
+}
+
+impl AppError {
+    pub fn new(error_type: ErrorType, message: impl Into<String>) -> Self {
+        Self {
+            message: Some(message.into()),
+            description: None,
+            error_type,
+        }
+    }
 }
 
 impl Display for AppError {
@@ -27,8 +185,43 @@ impl Display for AppError {
     }
 }
 
+impl std::error::Error for AppError {}
+
 impl From<AppError> for PyErr {
     fn from(error: AppError) -> Self {
-        PyException::new_err(format!("{error}"))
+        let text = error
+            .message
+            .clone()
+            .or_else(|| error.description.clone())
+            .unwrap_or_else(|| error.error_type.as_str().to_owned());
+        let err = error.error_type.new_err(text);
+        Python::attach(|py| {
+            let value = err.value(py);
+            let _ = value.setattr("message", error.message);
+            let _ = value.setattr("description", error.description);
+            let _ = value.setattr("error_type", error.error_type.as_str());
+        });
+        err
     }
-}
\ No newline at end of file
+}
+
+pub(crate) fn register(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    let py = m.py();
+    m.add("AmqpError", py.get_type::<AmqpError>())?;
+    m.add("NackError", py.get_type::<NackError>())?;
+    m.add("PublishTimeoutError", py.get_type::<PublishTimeoutError>())?;
+    m.add("ResponseTimeoutError", py.get_type::<ResponseTimeoutError>())?;
+    m.add("AutoReconnectError", py.get_type::<AutoReconnectError>())?;
+    m.add("ConnectionClosedError", py.get_type::<ConnectionClosedError>())?;
+    m.add("UnexpectedResultError", py.get_type::<UnexpectedResultError>())?;
+    m.add("InternalError", py.get_type::<InternalError>())?;
+    m.add("OperationTimeoutError", py.get_type::<OperationTimeoutError>())?;
+    m.add("UnsupportedContentTypeError", py.get_type::<UnsupportedContentTypeError>())?;
+    m.add("AlreadySettledError", py.get_type::<AlreadySettledError>())?;
+    m.add("StaleDeliveryError", py.get_type::<StaleDeliveryError>())?;
+    m.add("RemoteHandlerError", py.get_type::<RemoteHandlerError>())?;
+    m.add("NotFoundError", py.get_type::<NotFoundError>())?;
+    m.add("PreconditionFailedError", py.get_type::<PreconditionFailedError>())?;
+    m.add("AccessRefusedError", py.get_type::<AccessRefusedError>())?;
+    Ok(())
+}
@@ -0,0 +1,259 @@
+use amqp_client_rust::amqprs::{ByteArray, DecimalValue, FieldArray, FieldName, FieldTable, FieldValue, LongStr};
+use pyo3::{
+    exceptions::{PyTypeError, PyValueError},
+    prelude::*,
+    types::{PyBool, PyByteArray, PyBytes, PyDict, PyFloat, PyInt, PyList, PyString, PyTuple},
+    IntoPyObjectExt,
+};
+
+/// Converts an AMQP field table into a Python dict, recursing into nested
+/// tables and arrays.
+pub(crate) fn to_py<'py>(py: Python<'py>, table: &FieldTable) -> PyResult<Bound<'py, PyDict>> {
+    let dict = PyDict::new(py);
+    for (name, value) in table.as_ref() {
+        dict.set_item(name.to_string(), value_to_py(py, value)?)?;
+    }
+    Ok(dict)
+}
+
+/// Converts a Python dict into an AMQP field table. Keys must be strings of
+/// at most 255 bytes.
+pub(crate) fn from_py(dict: &Bound<'_, PyDict>) -> PyResult<FieldTable> {
+    let mut table = FieldTable::new();
+    for (key, value) in dict.iter() {
+        let key: String = key
+            .extract()
+            .map_err(|_| PyTypeError::new_err("header keys must be str"))?;
+        let name = FieldName::try_from(key.as_str())
+            .map_err(|_| PyValueError::new_err(format!("header key '{key}' is longer than 255 bytes")))?;
+        table.insert(name, value_from_py(&value)?);
+    }
+    Ok(table)
+}
+
+fn value_to_py<'py>(py: Python<'py>, value: &FieldValue) -> PyResult<Bound<'py, PyAny>> {
+    match value {
+        FieldValue::t(v) => v.into_bound_py_any(py),
+        FieldValue::b(v) => v.into_bound_py_any(py),
+        FieldValue::B(v) => v.into_bound_py_any(py),
+        FieldValue::s(v) => v.into_bound_py_any(py),
+        FieldValue::u(v) => v.into_bound_py_any(py),
+        FieldValue::I(v) => v.into_bound_py_any(py),
+        FieldValue::i(v) => v.into_bound_py_any(py),
+        FieldValue::l(v) => v.into_bound_py_any(py),
+        FieldValue::f(v) => v.into_bound_py_any(py),
+        FieldValue::d(v) => v.into_bound_py_any(py),
+        FieldValue::D(v) => {
+            // decimal = value * 10^-scale
+            let (scale, value) = decimal_parts(v)?;
+            let decimal = py.import("decimal")?.getattr("Decimal")?;
+            decimal.call1((value,))?.call_method1("scaleb", (-i32::from(scale),))
+        }
+        FieldValue::S(v) => v.to_string().into_bound_py_any(py),
+        FieldValue::A(v) => {
+            let items = Vec::<FieldValue>::from(v.clone())
+                .iter()
+                .map(|item| value_to_py(py, item))
+                .collect::<PyResult<Vec<_>>>()?;
+            PyList::new(py, items).map(Bound::into_any)
+        }
+        FieldValue::T(v) => {
+            let datetime = py.import("datetime")?;
+            let utc = datetime.getattr("timezone")?.getattr("utc")?;
+            datetime.getattr("datetime")?.call_method1("fromtimestamp", (*v, utc))
+        }
+        FieldValue::F(v) => to_py(py, v).map(Bound::into_any),
+        FieldValue::V => Ok(py.None().into_bound(py)),
+        FieldValue::x(v) => PyBytes::new(py, &Vec::<u8>::from(v.clone())).into_bound_py_any(py),
+    }
+}
+
+fn value_from_py(value: &Bound<'_, PyAny>) -> PyResult<FieldValue> {
+    let py = value.py();
+    if value.is_none() {
+        return Ok(FieldValue::V);
+    }
+    // bool is a subclass of int, so it must be checked first.
+    if value.is_instance_of::<PyBool>() {
+        return Ok(FieldValue::t(value.extract()?));
+    }
+    if value.is_instance_of::<PyInt>() {
+        let v: i64 = value
+            .extract()
+            .map_err(|_| PyValueError::new_err("header integers must fit in a signed 64-bit integer"))?;
+        return Ok(FieldValue::l(v));
+    }
+    if value.is_instance_of::<PyFloat>() {
+        return Ok(FieldValue::d(value.extract()?));
+    }
+    if let Ok(s) = value.cast::<PyString>() {
+        let s = s.to_str()?.to_owned();
+        return LongStr::try_from(s)
+            .map(FieldValue::S)
+            .map_err(|_| PyValueError::new_err("header string is too long"));
+    }
+    if let Ok(b) = value.cast::<PyBytes>() {
+        return Ok(FieldValue::x(ByteArray::try_from(b.as_bytes().to_vec())
+            .map_err(|_| PyValueError::new_err("header bytes value is too long"))?));
+    }
+    if let Ok(b) = value.cast::<PyByteArray>() {
+        return Ok(FieldValue::x(ByteArray::try_from(b.to_vec())
+            .map_err(|_| PyValueError::new_err("header bytes value is too long"))?));
+    }
+    if let Ok(dict) = value.cast::<PyDict>() {
+        return Ok(FieldValue::F(from_py(dict)?));
+    }
+    if value.is_instance_of::<PyList>() || value.is_instance_of::<PyTuple>() {
+        let items = value
+            .try_iter()?
+            .map(|item| value_from_py(&item?))
+            .collect::<PyResult<Vec<_>>>()?;
+        return FieldArray::try_from(items)
+            .map(FieldValue::A)
+            .map_err(|_| PyValueError::new_err("header array is too long"));
+    }
+    if value.is_instance(&py.import("decimal")?.getattr("Decimal")?)? {
+        return decimal_from_py(value).map(FieldValue::D);
+    }
+    if value.is_instance(&py.import("datetime")?.getattr("datetime")?)? {
+        let seconds: f64 = value.call_method0("timestamp")?.extract()?;
+        if seconds < 0.0 {
+            return Err(PyValueError::new_err("header timestamps must not be before the unix epoch"));
+        }
+        return Ok(FieldValue::T(seconds as u64));
+    }
+    Err(PyTypeError::new_err(format!(
+        "unsupported header value type '{}'",
+        value.get_type().name()?
+    )))
+}
+
+fn decimal_from_py(value: &Bound<'_, PyAny>) -> PyResult<DecimalValue> {
+    // Decimal.as_tuple() -> (sign, digits, exponent)
+    let (sign, digits, exponent): (u8, Vec<u32>, i64) = value.call_method0("as_tuple")?.extract()?;
+    let scale = u8::try_from(-exponent.min(0))
+        .map_err(|_| PyValueError::new_err("header decimals support at most 255 fractional digits"))?;
+    let overflow = || PyValueError::new_err("header decimal does not fit in a signed 32-bit integer");
+    // A signed 32-bit integer has at most 10 digits, so a longer value
+    // overflows without padding it out to its exponent first. Zero stays zero
+    // whatever its exponent.
+    let significant = digits.iter().skip_while(|&&digit| digit == 0).count() as i64;
+    if significant > 0 && significant + exponent.max(0) > 10 {
+        return Err(overflow());
+    }
+    let padding = if significant == 0 { 0 } else { exponent.max(0) as usize };
+    // Accumulated as i64 so that i32::MIN, one larger in magnitude than
+    // i32::MAX, still fits before the sign is applied.
+    let mut magnitude: i64 = 0;
+    for digit in digits.into_iter().chain(std::iter::repeat_n(0, padding)) {
+        magnitude = magnitude * 10 + i64::from(digit);
+        if magnitude > i64::from(i32::MAX) + 1 {
+            return Err(overflow());
+        }
+    }
+    let unscaled = i32::try_from(if sign != 0 { -magnitude } else { magnitude }).map_err(|_| overflow())?;
+    Ok(DecimalValue::new(scale, unscaled))
+}
+
+/// Scale and unscaled value of a decimal. amqp_serde keeps both private, so
+/// they are read back from its wire encoding: the scale octet followed by the
+/// big-endian value.
+fn decimal_parts(decimal: &DecimalValue) -> PyResult<(u8, i32)> {
+    match amqp_serde::to_bytes(decimal).as_deref() {
+        Ok(&[scale, a, b, c, d]) => Ok((scale, i32::from_be_bytes([a, b, c, d]))),
+        _ => Err(PyValueError::new_err(format!("unreadable header {decimal}"))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    fn eval<'py>(py: Python<'py>, code: &CStr) -> Bound<'py, PyAny> {
+        let globals = PyDict::new(py);
+        py.run(c"import datetime, decimal", Some(&globals), None).unwrap();
+        py.eval(code, Some(&globals), None).unwrap()
+    }
+
+    fn field(name: &str) -> FieldName {
+        FieldName::try_from(name).unwrap()
+    }
+
+    /// Converts a Python dict expression into a table and back, asserting the
+    /// result equals the original.
+    fn round_trip(py: Python<'_>, code: &CStr) -> FieldTable {
+        let dict = eval(py, code);
+        let table = from_py(dict.cast().unwrap()).unwrap();
+        assert!(to_py(py, &table).unwrap().eq(&dict).unwrap());
+        table
+    }
+
+    #[test]
+    fn converts_bool_before_int() {
+        Python::initialize();
+        Python::attach(|py| {
+            let table = round_trip(py, c"{'flag': True, 'count': 1, 'none': None}");
+            let values = table.as_ref();
+            assert_eq!(values.get(&field("flag")), Some(&FieldValue::t(true)));
+            assert_eq!(values.get(&field("count")), Some(&FieldValue::l(1)));
+            assert_eq!(values.get(&field("none")), Some(&FieldValue::V));
+            let converted = to_py(py, &table).unwrap();
+            assert!(converted.get_item("flag").unwrap().unwrap().is_instance_of::<PyBool>());
+        });
+    }
+
+    #[test]
+    fn converts_nested_tables_and_arrays() {
+        Python::initialize();
+        Python::attach(|py| {
+            let table = round_trip(py, c"{'outer': {'inner': {'key': 'value'}}, 'items': [1, 'two', [3.5, b'four']]}");
+            let Some(FieldValue::A(items)) = table.as_ref().get(&field("items")) else {
+                panic!("items is not an array");
+            };
+            assert_eq!(Vec::<FieldValue>::from(items.clone()).len(), 3);
+            let Some(FieldValue::F(outer)) = table.as_ref().get(&field("outer")) else {
+                panic!("outer is not a table");
+            };
+            assert!(matches!(outer.as_ref().get(&field("inner")), Some(FieldValue::F(_))));
+        });
+    }
+
+    #[test]
+    fn converts_decimals() {
+        Python::initialize();
+        Python::attach(|py| {
+            let table = round_trip(py, c"{'price': decimal.Decimal('-12.345'), 'whole': decimal.Decimal('42')}");
+            assert_eq!(
+                table.as_ref().get(&field("price")),
+                Some(&FieldValue::D(DecimalValue::new(3, -12345)))
+            );
+            let dict = eval(py, c"{'big': decimal.Decimal('1E+10')}");
+            assert!(from_py(dict.cast().unwrap()).is_err());
+            let dict = eval(py, c"{'huge': decimal.Decimal('1E+1000000000')}");
+            assert!(from_py(dict.cast().unwrap()).is_err());
+            let dict = eval(py, c"{'zero': decimal.Decimal('0E+1000000000')}");
+            let table = from_py(dict.cast().unwrap()).unwrap();
+            assert_eq!(table.as_ref().get(&field("zero")), Some(&FieldValue::D(DecimalValue::new(0, 0))));
+        });
+    }
+
+    #[test]
+    fn reads_decimal_parts() {
+        assert_eq!(decimal_parts(&DecimalValue::new(3, -12345)).unwrap(), (3, -12345));
+        assert_eq!(decimal_parts(&DecimalValue::new(255, i32::MIN)).unwrap(), (255, i32::MIN));
+    }
+
+    #[test]
+    fn converts_datetimes_as_utc_seconds() {
+        Python::initialize();
+        Python::attach(|py| {
+            let table = round_trip(
+                py,
+                c"{'at': datetime.datetime(2024, 1, 2, 3, 4, 5, tzinfo=datetime.timezone.utc)}",
+            );
+            assert_eq!(table.as_ref().get(&field("at")), Some(&FieldValue::T(1_704_164_645)));
+        });
+    }
+}
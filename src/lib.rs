@@ -1,18 +1,39 @@
-use std::{fs::File, io::BufReader, path::Path, sync::Arc};
+use std::{fs::File, future::Future, io::BufReader, path::Path, pin::Pin, sync::Arc};
 
 use amqp_client_rust::{
-    amqprs::{tls::TlsAdaptor as RuTlsAdaptor}, api::{
-        eventbus::AsyncEventbusRabbitMQ as RuAsyncEventbusRabbitMQ,
-        utils::{ContentEncoding as RuContentEncoding, DeliveryMode as RuDeliveryMode, Message as RuMessage},
-    }, domain::config::{
-        Config as RuConfig, ConfigOptions as RuConfigOptions, QoSConfig as RuQoSConfig,
-    }
+    amqprs::{tls::TlsAdaptor as RuTlsAdaptor, FieldTable}, api::{
+        utils::{ContentEncoding as RuContentEncoding, DeliveryMode as RuDeliveryMode},
+    },
 };
 use pyo3::{
-    exceptions::PyValueError, prelude::*, types::{PyBytes, PyString}
+    exceptions::{PyStopAsyncIteration, PyValueError}, intern, prelude::*, types::{PyBytes, PyCFunction, PyDict, PyString}
 };
+mod bus;
+mod connection;
+mod consumer;
+mod credentials;
 pub mod exceptions;
-use exceptions::AppError;
+mod field_table;
+pub mod message;
+mod publisher;
+mod rpc;
+mod settings;
+mod streaming;
+pub mod subscription;
+pub mod sync_eventbus;
+pub mod topology;
+mod uri;
+use bus::{subscribe_spec, Bus, PublishRequest, RpcRequest};
+use consumer::{Handler, HandlerError, HandlerFuture, Reply, SubscribeSpec};
+use credentials::CredentialsProvider;
+use message::{DeliveryInfo, Message};
+use rpc::RemoteError;
+use settings::Settings;
+use streaming::ReplyStream;
+use subscription::{MessageStream, Subscription};
+use sync_eventbus::SyncEventbus;
+use topology::{ExchangeKind, ExchangeSpec, ExchangeType, Overflow, QueueInfo, QueueOptions, QueueSpec, QueueType};
+use uri::AmqpUri;
 use rustls::{ClientConfig, RootCertStore, pki_types::{CertificateDer, PrivateKeyDer}};
 use tokio_rustls::TlsConnector;
 use std::path::PathBuf;
@@ -26,7 +47,7 @@ use std::path::PathBuf;
 #[pyclass(skip_from_py_object)]
 #[derive(Clone)]
 struct AsyncEventbus {
-    eventbus: Arc<RuAsyncEventbusRabbitMQ>,
+    bus: Bus,
 }
 
 #[pyclass(from_py_object, get_all, set_all)]
@@ -44,53 +65,6 @@ impl From<DeliveryMode> for RuDeliveryMode {
     }
 }
 
-#[pyclass(from_py_object)]
-#[derive(Debug, Clone)]
-pub struct Message {
-    body: Arc<[u8]>,
-    content_type: Option<String>,
-}
-impl From<RuMessage> for Message {
-    fn from(msg: RuMessage) -> Self {
-        Self {
-            body: msg.body,
-            content_type: msg.content_type,
-        }
-    }
-}
-impl From<Message> for RuMessage {
-    fn from(msg: Message) -> Self {
-        Self {
-            body: msg.body,
-            content_type: msg.content_type,
-        }
-    }
-}
-
-#[pymethods]
-impl Message {
-    #[staticmethod]
-    fn new<'py>(body: Payload<'py>, content_type: Option<String>) -> PyResult<Self> {
-        let body: Arc<[u8]> = match body {
-            Payload::Bytes(b) => b.as_bytes().into(),
-            Payload::Str(s) => s.to_str()?.as_bytes().into(),
-        };
-        Ok(Self {
-            body,
-            content_type,
-        })
-    }
-
-    #[getter]
-    fn body<'py>(slf: PyRef<'py, Self>) -> Bound<'py, PyBytes> {
-        PyBytes::new(slf.py(), &slf.body)
-    }
-    #[getter]
-    fn content_type(&self) -> Option<&str> {
-        self.content_type.as_deref()
-    }
-}
-
 #[pyclass(from_py_object, get_all, set_all)]
 #[derive(Debug, Clone)]
 pub enum ContentEncoding {
@@ -99,9 +73,9 @@ pub enum ContentEncoding {
     Zlib,
     Null,
 }
-impl Into<RuContentEncoding> for ContentEncoding {
-    fn into(self) -> RuContentEncoding {
-        match self {
+impl From<ContentEncoding> for RuContentEncoding {
+    fn from(encoding: ContentEncoding) -> Self {
+        match encoding {
             ContentEncoding::Zstd => RuContentEncoding::Zstd,
             ContentEncoding::Lz4 => RuContentEncoding::Lz4,
             ContentEncoding::Zlib => RuContentEncoding::Zlib,
@@ -110,6 +84,17 @@ impl Into<RuContentEncoding> for ContentEncoding {
     }
 }
 
+impl ContentEncoding {
+    /// Value carried in the `content_encoding` property, as the inner crate
+    /// writes it; `None` for uncompressed bodies.
+    pub(crate) fn as_property(&self) -> Option<&'static str> {
+        match self {
+            ContentEncoding::Null => None,
+            encoding => Some(RuContentEncoding::from(encoding.clone()).as_str()),
+        }
+    }
+}
+
 #[pyclass(from_py_object, get_all, set_all)]
 #[derive(Debug, Clone)]
 pub struct ConfigOptions {
@@ -128,15 +113,6 @@ impl ConfigOptions {
         }
     }
 }
-impl From<ConfigOptions> for RuConfigOptions {
-    fn from(options: ConfigOptions) -> Self {
-        Self {
-            queue_name: options.queue_name,
-            rpc_exchange_name: options.rpc_exchange_name,
-            rpc_queue_name: options.rpc_queue_name,
-        }
-    }
-}
 
 #[pyclass(from_py_object, get_all, set_all)]
 #[derive(Clone)]
@@ -148,76 +124,437 @@ pub struct Config {
     pub virtual_host: String,
     pub options: ConfigOptions,
     pub tls_adaptor: Option<TlsAdaptor>, // Placeholder for TLS adaptor
+    /// Heartbeat interval in seconds; `None` accepts the broker's.
+    pub heartbeat: Option<u16>,
+    /// Milliseconds one connection attempt may take.
+    pub connection_timeout: Option<u64>,
+    pub auth_mechanism: AuthMechanism,
+    /// Nodes to connect to instead of `host` and `port`, each tried in
+    /// turn when connecting and after the current one drops.
+    pub hosts: Vec<Endpoint>,
+    pub host_selection: HostSelection,
+    /// Shown for the connection in the management UI.
+    pub connection_name: Option<String>,
+    /// Supplies the password, e.g. an OAuth 2.0 access token, instead of
+    /// `password`.
+    pub credentials_provider: Option<CredentialsProvider>,
 }
 #[pymethods]
 impl Config {
     #[new]
-    #[pyo3(signature = (host, port, username, password, virtual_host, options, tls_adaptor=None))]
+    #[pyo3(signature = (host, port, username, password, virtual_host, options, tls_adaptor=None, heartbeat=None, connection_timeout=None, auth_mechanism=AuthMechanism::Plain, hosts=Vec::new(), host_selection=HostSelection::InOrder, connection_name=None, credentials_provider=None))]
+    #[allow(clippy::too_many_arguments)]
     fn new(
         host: String,
         port: u16,
-        username: String,
-        password: String,
+        username: Option<String>,
+        password: Option<String>,
         virtual_host: String,
         options: ConfigOptions,
         tls_adaptor: Option<TlsAdaptor>,
-    ) -> Self {
-        Self {
+        heartbeat: Option<u16>,
+        connection_timeout: Option<u64>,
+        auth_mechanism: AuthMechanism,
+        hosts: Vec<Endpoint>,
+        host_selection: HostSelection,
+        connection_name: Option<String>,
+        credentials_provider: Option<CredentialsProvider>,
+    ) -> PyResult<Self> {
+        // EXTERNAL authenticates with the client certificate alone.
+        let external = auth_mechanism == AuthMechanism::External;
+        if username.is_none() && !external {
+            return Err(PyValueError::new_err("username is required unless auth_mechanism is EXTERNAL"));
+        }
+        if password.is_none() && !external && credentials_provider.is_none() {
+            return Err(PyValueError::new_err(
+                "password is required unless auth_mechanism is EXTERNAL or a credentials_provider is given",
+            ));
+        }
+        let config = Self {
             host,
             port,
-            username,
-            password,
+            username: username.unwrap_or_default(),
+            password: password.unwrap_or_default(),
             virtual_host,
             options,
             tls_adaptor,
+            heartbeat,
+            connection_timeout,
+            auth_mechanism,
+            hosts,
+            host_selection,
+            connection_name,
+            credentials_provider,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Builds a config from a RabbitMQ URI. For `amqps` without a
+    /// `tls_adaptor`, one is built from the `cacertfile`, `certfile`,
+    /// `keyfile` and `server_name_indication` query parameters.
+    #[staticmethod]
+    #[pyo3(signature = (url, options, tls_adaptor=None))]
+    fn from_url(url: &str, options: ConfigOptions, tls_adaptor: Option<TlsAdaptor>) -> PyResult<Self> {
+        let config = Self::parse_url(url, options, tls_adaptor)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The config as a RabbitMQ URI, with the password redacted. TLS file
+    /// paths are not kept by the adaptor, so they are not part of it.
+    fn to_url(&self) -> String {
+        let mut query = Vec::new();
+        if let Some(heartbeat) = self.heartbeat {
+            query.push(("heartbeat".to_owned(), heartbeat.to_string()));
+        }
+        if let Some(connection_timeout) = self.connection_timeout {
+            query.push(("connection_timeout".to_owned(), connection_timeout.to_string()));
+        }
+        if let Some(connection_name) = &self.connection_name {
+            query.push(("connection_name".to_owned(), connection_name.clone()));
+        }
+        if !matches!(self.auth_mechanism, AuthMechanism::Plain) {
+            query.push(("auth_mechanism".to_owned(), self.auth_mechanism.name().to_owned()));
+        }
+        AmqpUri {
+            tls: self.tls_adaptor.is_some(),
+            host: self.host.clone(),
+            port: self.port,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            virtual_host: self.virtual_host.clone(),
+            query,
+        }
+        .to_redacted_string()
+    }
+
+    fn __repr__(&self) -> String {
+        format!("Config({})", self.to_url())
+    }
+
+    /// Reads the config from `{prefix}URL` or `{prefix}HOST`, `{prefix}PORT`
+    /// and so on, with `{prefix}OPTIONS_QUEUE_NAME` style names for the
+    /// `options` and `tls` sections.
+    #[staticmethod]
+    #[pyo3(signature = (prefix="AMQP_"))]
+    fn from_env(prefix: &str) -> PyResult<Self> {
+        Settings::from_env(prefix).config()
+    }
+
+    /// Reads the config from a TOML, YAML or JSON file.
+    #[staticmethod]
+    fn from_file(py: Python<'_>, path: PathBuf) -> PyResult<Self> {
+        Settings::from_file(py, &path)?.config()
+    }
+}
+
+impl Config {
+    /// `from_url` without validation, for callers that complete the config
+    /// afterwards.
+    pub(crate) fn parse_url(url: &str, options: ConfigOptions, tls_adaptor: Option<TlsAdaptor>) -> PyResult<Self> {
+        let uri = AmqpUri::parse(url).map_err(PyValueError::new_err)?;
+        let mut config = Self::new(
+            uri.host,
+            uri.port,
+            Some(uri.username),
+            Some(uri.password),
+            uri.virtual_host,
+            options,
+            tls_adaptor,
+            None,
+            None,
+            AuthMechanism::Plain,
+            Vec::new(),
+            HostSelection::InOrder,
+            None,
+            None,
+        )?;
+        let mut tls_files = UriTlsFiles::default();
+        for (key, value) in uri.query {
+            let invalid = |expected: &str| PyValueError::new_err(format!("URI parameter '{key}' must be {expected}, got '{value}'"));
+            match key.as_str() {
+                "heartbeat" => config.heartbeat = Some(value.parse().map_err(|_| invalid("a number of seconds"))?),
+                // amqprs always accepts the limits the broker proposes.
+                "frame_max" | "channel_max" => {
+                    return Err(PyValueError::new_err(format!(
+                        "URI parameter '{key}' is not supported, the broker's value is used"
+                    )))
+                }
+                "connection_timeout" => {
+                    config.connection_timeout = Some(value.parse().map_err(|_| invalid("a number of milliseconds"))?)
+                }
+                "connection_name" => config.connection_name = Some(value),
+                "auth_mechanism" => {
+                    config.auth_mechanism = AuthMechanism::from_name(&value)
+                        .ok_or_else(|| invalid("one of PLAIN, AMQPLAIN or EXTERNAL"))?
+                }
+                "cacertfile" => tls_files.ca = Some(value.into()),
+                "certfile" => tls_files.cert = Some(value.into()),
+                "keyfile" => tls_files.key = Some(value.into()),
+                "server_name_indication" => tls_files.domain = Some(value),
+                _ => return Err(PyValueError::new_err(format!("unsupported URI parameter '{key}'"))),
+            }
+        }
+        if !tls_files.is_empty() {
+            if !uri.tls {
+                return Err(PyValueError::new_err("TLS parameters require an amqps:// URI"));
+            }
+            if config.tls_adaptor.is_some() {
+                return Err(PyValueError::new_err("pass either tls_adaptor or TLS URI parameters, not both"));
+            }
+        }
+        if uri.tls && config.tls_adaptor.is_none() {
+            let domain = tls_files.domain.unwrap_or_else(|| config.host.clone());
+            config.tls_adaptor = Some(match (tls_files.cert, tls_files.key) {
+                (Some(cert), Some(key)) => TlsAdaptor::with_client_auth(tls_files.ca, cert, key, domain, None)?,
+                (None, None) => TlsAdaptor::without_client_auth(tls_files.ca, domain, None)?,
+                _ => return Err(PyValueError::new_err("URI parameters 'certfile' and 'keyfile' must be given together")),
+            });
+        }
+        Ok(config)
+    }
+
+    /// EXTERNAL authenticates with the client certificate, so it needs a
+    /// `TlsAdaptor` that presents one.
+    pub(crate) fn validate(&self) -> PyResult<()> {
+        match self.invalid_field() {
+            Some((_, reason)) => Err(PyValueError::new_err(reason)),
+            None => Ok(()),
+        }
+    }
+
+    /// The first field that makes the config unusable, and why.
+    pub(crate) fn invalid_field(&self) -> Option<(&'static str, &'static str)> {
+        if self.auth_mechanism == AuthMechanism::External && !self.tls_adaptor.as_ref().is_some_and(|t| t.client_auth) {
+            return Some((
+                "tls_adaptor",
+                "auth_mechanism EXTERNAL needs a tls_adaptor with a client certificate, see TlsAdaptor.with_client_auth",
+            ));
+        }
+        None
+    }
+
+    /// The nodes to connect to: `hosts`, or `host` and `port` without them.
+    pub(crate) fn endpoints(&self) -> Vec<Endpoint> {
+        if self.hosts.is_empty() {
+            vec![Endpoint::Plain(self.host.clone(), self.port)]
+        } else {
+            self.hosts.clone()
         }
     }
 }
 
-impl From<Config> for RuConfig {
-    fn from(config: Config) -> Self {
-        Self {
-            host: config.host,
-            port: config.port,
-            username: config.username,
-            password: config.password,
-            virtual_host: config.virtual_host,
-            options: config.options.into(),
-            tls_adaptor: config.tls_adaptor.map(|t| t.into()),
+/// A `(host, port)` or `(host, port, tls_domain)` node of a cluster. Without
+/// a domain the `TlsAdaptor`'s own is verified.
+#[derive(Debug, Clone, FromPyObject, IntoPyObject)]
+pub enum Endpoint {
+    WithTlsDomain(String, u16, String),
+    Plain(String, u16),
+}
+impl Endpoint {
+    pub(crate) fn host(&self) -> &str {
+        match self {
+            Self::WithTlsDomain(host, _, _) | Self::Plain(host, _) => host,
         }
     }
+
+    pub(crate) fn port(&self) -> u16 {
+        match self {
+            Self::WithTlsDomain(_, port, _) | Self::Plain(_, port) => *port,
+        }
+    }
+
+    pub(crate) fn tls_domain(&self) -> Option<&str> {
+        match self {
+            Self::WithTlsDomain(_, _, domain) => Some(domain),
+            Self::Plain(..) => None,
+        }
+    }
+}
+
+/// Order the `hosts` of a config are tried in.
+#[pyclass(from_py_object, eq, eq_int)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum HostSelection {
+    /// The listed order, moving on to the next node when one drops.
+    InOrder,
+    /// A new random order on every connection attempt, spreading clients
+    /// over the cluster.
+    Random,
+}
+
+/// Certificates a `TlsAdaptor` trusts besides its own CA certificates.
+#[pyclass(from_py_object, eq, eq_int)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrustRoots {
+    /// The Mozilla roots compiled into the library.
+    Webpki,
+    /// The operating system's trust store, including CAs installed there
+    /// by an administrator.
+    Native,
 }
+
+impl TrustRoots {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "webpki" => Some(Self::Webpki),
+            "native" => Some(Self::Native),
+            _ => None,
+        }
+    }
+}
+
+/// TLS query parameters of a connection URI.
+#[derive(Default)]
+struct UriTlsFiles {
+    ca: Option<PathBuf>,
+    cert: Option<PathBuf>,
+    key: Option<PathBuf>,
+    domain: Option<String>,
+}
+
+impl UriTlsFiles {
+    fn is_empty(&self) -> bool {
+        self.ca.is_none() && self.cert.is_none() && self.key.is_none() && self.domain.is_none()
+    }
+}
+
+/// SASL mechanism the connection authenticates with.
+#[pyclass(from_py_object, eq, eq_int)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthMechanism {
+    Plain,
+    AMQPlain,
+    External,
+}
+impl AuthMechanism {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_uppercase().as_str() {
+            "PLAIN" => Some(Self::Plain),
+            "AMQPLAIN" => Some(Self::AMQPlain),
+            "EXTERNAL" => Some(Self::External),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Plain => "PLAIN",
+            Self::AMQPlain => "AMQPLAIN",
+            Self::External => "EXTERNAL",
+        }
+    }
+}
+
+/// PEM text passed as `str` or `bytes`.
+#[derive(FromPyObject)]
+enum Pem {
+    Bytes(Vec<u8>),
+    Text(String),
+}
+
+impl Pem {
+    fn as_bytes(&self) -> &[u8] {
+        match self {
+            Pem::Bytes(bytes) => bytes,
+            Pem::Text(text) => text.as_bytes(),
+        }
+    }
+}
+
 #[pyclass(from_py_object)]
 #[derive(Clone)]
 pub struct TlsAdaptor {
     pub(crate) inner: Arc<RuTlsAdaptor>,
+    connector: TlsConnector,
+    domain: String,
+    /// Whether a client certificate is presented, as SASL EXTERNAL requires.
+    client_auth: bool,
 }
 
 impl TlsAdaptor {
-    fn build_root_store(root_ca_cert: Option<&Path>) -> std::io::Result<RootCertStore> {
-        let mut root_store = RootCertStore::empty();
-        if let Some(root_ca_cert) = root_ca_cert {
-            let mut pem = BufReader::new(File::open(root_ca_cert)?);
-
-            let certs = rustls_pemfile::certs(&mut pem);
-
-            let trust_anchors = certs
-                .into_iter()
-                .map(|cert| {
-                    cert.map(|cert| {
-                        let anchor = webpki::anchor_from_trusted_cert(&cert).unwrap().to_owned();
-
-                        rustls_pki_types::TrustAnchor {
-                            subject: anchor.subject,
-                            subject_public_key_info: anchor.subject_public_key_info,
-                            name_constraints: anchor.name_constraints,
-                        }
-                    })
+    fn new(config: ClientConfig, domain: String, client_auth: bool) -> Self {
+        let connector = TlsConnector::from(Arc::new(config));
+        Self {
+            inner: Arc::new(RuTlsAdaptor::new(connector.clone(), domain.clone())),
+            connector,
+            domain,
+            client_auth,
+        }
+    }
+
+    /// The adaptor verifying the server as `domain`, or as its own domain.
+    pub(crate) fn for_domain(&self, domain: Option<&str>) -> RuTlsAdaptor {
+        match domain {
+            Some(domain) if domain != self.domain => RuTlsAdaptor::new(self.connector.clone(), domain.to_owned()),
+            _ => RuTlsAdaptor::clone(&self.inner),
+        }
+    }
+
+    /// Builds the adaptor trusting `ca_certs` and `roots`, and presenting
+    /// `client_auth` when given.
+    fn build(
+        ca_certs: Option<Vec<CertificateDer<'static>>>,
+        client_auth: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
+        roots: Option<Vec<TrustRoots>>,
+        domain: String,
+    ) -> PyResult<Self> {
+        let ca_anchors = ca_certs.as_deref().map(TlsAdaptor::ca_anchors).transpose()?;
+        let root_store = TlsAdaptor::build_root_store(ca_anchors, roots)?;
+        TlsAdaptor::with_root_store(root_store, client_auth, domain)
+    }
+
+    fn with_root_store(
+        root_store: RootCertStore,
+        client_auth: Option<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)>,
+        domain: String,
+    ) -> PyResult<Self> {
+        install_crypto_provider()?;
+        let builder = ClientConfig::builder().with_root_certificates(root_store);
+        let has_client_auth = client_auth.is_some();
+        let config = match client_auth {
+            Some((certs, key)) => builder
+                .with_client_auth_cert(certs, key)
+                .map_err(|e| PyValueError::new_err(format!("invalid client certificate or key: {e}")))?,
+            None => builder.with_no_client_auth(),
+        };
+        Ok(Self::new(config, domain, has_client_auth))
+    }
+
+    fn ca_anchors(ca_certs: &[CertificateDer<'static>]) -> PyResult<Vec<rustls_pki_types::TrustAnchor<'static>>> {
+        ca_certs
+            .iter()
+            .map(|cert| {
+                let anchor = webpki::anchor_from_trusted_cert(cert)
+                    .map_err(|e| PyValueError::new_err(format!("invalid CA certificate: {e}")))?
+                    .to_owned();
+
+                Ok(rustls_pki_types::TrustAnchor {
+                    subject: anchor.subject,
+                    subject_public_key_info: anchor.subject_public_key_info,
+                    name_constraints: anchor.name_constraints,
                 })
-                .collect::<std::io::Result<Vec<rustls_pki_types::TrustAnchor>>>()?;
+            })
+            .collect()
+    }
 
-            root_store.roots.extend(trust_anchors);
-        } else {
+    /// Without `roots`, the CA certificates replace the bundled webpki roots
+    /// and the webpki roots are trusted when there are none.
+    fn build_root_store(
+        ca_anchors: Option<Vec<rustls_pki_types::TrustAnchor<'static>>>,
+        roots: Option<Vec<TrustRoots>>,
+    ) -> PyResult<RootCertStore> {
+        let roots = match roots {
+            Some(roots) => roots,
+            None if ca_anchors.is_some() => Vec::new(),
+            None => vec![TrustRoots::Webpki],
+        };
+        let mut root_store = RootCertStore::empty();
+        if let Some(ca_anchors) = ca_anchors {
+            root_store.roots.extend(ca_anchors);
+        }
+        if roots.contains(&TrustRoots::Webpki) {
             root_store
                 .roots
                 .extend(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
@@ -228,37 +565,51 @@ impl TlsAdaptor {
                     }
                 }));
         }
+        if roots.contains(&TrustRoots::Native) {
+            let native = rustls_native_certs::load_native_certs();
+            if native.certs.is_empty() {
+                let reason = native.errors.first().map_or("it is empty".to_owned(), ToString::to_string);
+                return Err(PyValueError::new_err(format!("could not load the system trust store: {reason}")));
+            }
+            // System stores hold certificates webpki cannot parse; the rest
+            // are still trusted.
+            root_store.add_parsable_certificates(native.certs);
+        }
+        if root_store.is_empty() {
+            return Err(PyValueError::new_err("no trusted root certificates, give CA certificates or roots"));
+        }
         Ok(root_store)
     }
 
-    fn build_client_certificates<'a>(
-        client_cert: &Path,
-    ) -> std::io::Result<Vec<CertificateDer<'a>>> {
-        let file = File::open(client_cert)?;
-        let mut pem = BufReader::new(file);
-        let raw_certs = rustls_pemfile::certs(&mut pem);
-
-        let certs: Vec<CertificateDer> = raw_certs
-            .into_iter()
-            .collect::<std::io::Result<Vec<CertificateDer>>>()?;
+    /// The certificates of a PEM file or string, `source` naming it in errors.
+    fn read_certificates(
+        rd: &mut dyn std::io::BufRead,
+        source: &str,
+    ) -> PyResult<Vec<CertificateDer<'static>>> {
+        let certs = rustls_pemfile::certs(rd).collect::<std::io::Result<Vec<CertificateDer>>>()?;
+        if certs.is_empty() {
+            return Err(PyValueError::new_err(format!("no PEM certificate found in {source}")));
+        }
         Ok(certs)
     }
 
-    fn build_client_private_keys<'a>(
-        client_private_key: &Path,
-    ) -> std::io::Result<Vec<PrivateKeyDer<'a>>> {
-        let mut pem = BufReader::new(File::open(client_private_key)?);
-        let keys = TlsAdaptor::read_private_keys_from_pem(&mut pem)?;
-        let keys = keys
+    /// The first private key of a PEM file or string, read in memory only.
+    fn read_private_key(rd: &mut dyn std::io::BufRead, source: &str) -> PyResult<PrivateKeyDer<'static>> {
+        let key = TlsAdaptor::read_private_keys_from_pem(rd)?
             .into_iter()
-            .map(|c| {
-                PrivateKeyDer::try_from(c)
-                    .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
-            })
-            .collect::<std::io::Result<Vec<PrivateKeyDer>>>()?;
+            .next()
+            .ok_or_else(|| PyValueError::new_err(format!("no valid private key found in {source}")))?;
+        PrivateKeyDer::try_from(key).map_err(|e| PyValueError::new_err(format!("{source}: {e}")))
+    }
 
-        Ok(keys)
+    fn read_file<T>(
+        path: &Path,
+        read: fn(&mut dyn std::io::BufRead, &str) -> PyResult<T>,
+    ) -> PyResult<T> {
+        let mut pem = BufReader::new(File::open(path)?);
+        read(&mut pem, &path.display().to_string())
     }
+
     fn read_private_keys_from_pem(
         rd: &mut dyn std::io::BufRead,
     ) -> Result<Vec<Vec<u8>>, std::io::Error> {
@@ -283,6 +634,10 @@ impl TlsAdaptor {
 }
 
 fn install_crypto_provider() -> Result<(), PyErr> {
+    // Every adaptor after the first finds the provider installed.
+    if rustls::crypto::CryptoProvider::get_default().is_some() {
+        return Ok(());
+    }
     #[cfg(target_vendor = "apple")]
     rustls::crypto::ring::default_provider()
     .install_default().map_err(|_| PyValueError::new_err("Error on install crypto provider for tls"))?;
@@ -295,41 +650,86 @@ fn install_crypto_provider() -> Result<(), PyErr> {
 #[pymethods]
 impl TlsAdaptor {
     #[staticmethod]
+    #[pyo3(signature = (ca_path, cert_path, key_path, domain, roots=None))]
     pub fn with_client_auth(
         ca_path: Option<PathBuf>,
         cert_path: PathBuf,
         key_path: PathBuf,
         domain: String,
+        roots: Option<Vec<TrustRoots>>,
     ) -> PyResult<Self> {
-        install_crypto_provider()?;
-        let root_cert_store: RootCertStore = TlsAdaptor::build_root_store(ca_path.as_deref())?;
-        let client_certs: Vec<CertificateDer> = TlsAdaptor::build_client_certificates(&cert_path)?;
-        let client_keys: Vec<PrivateKeyDer> = TlsAdaptor::build_client_private_keys(&key_path)?;
-        let config = ClientConfig::builder()
-            .with_root_certificates(root_cert_store)
-            .with_client_auth_cert(client_certs, client_keys.into_iter().next().ok_or_else(|| PyValueError::new_err("No valid private keys found in the provided key file"))?)
-            .unwrap();
-        let connector = TlsConnector::from(Arc::new(config));
-
-        let inner = Arc::new(
-            RuTlsAdaptor::new(connector, domain)
-        );
-
-        Ok(Self { inner })
+        let ca_certs = ca_path
+            .map(|path| TlsAdaptor::read_file(&path, TlsAdaptor::read_certificates))
+            .transpose()?;
+        let client_certs = TlsAdaptor::read_file(&cert_path, TlsAdaptor::read_certificates)?;
+        let client_key = TlsAdaptor::read_file(&key_path, TlsAdaptor::read_private_key)?;
+        TlsAdaptor::build(ca_certs, Some((client_certs, client_key)), roots, domain)
     }
     #[staticmethod]
-    pub fn without_client_auth(root_ca_cert: Option<PathBuf>, domain: String) -> PyResult<Self> {
-        install_crypto_provider()?;
-        let inner = Arc::new(
-            RuTlsAdaptor::without_client_auth(root_ca_cert.as_deref(), domain)
-                .map_err(|e| PyValueError::new_err(e.to_string()))?,
-        );
-        Ok(Self { inner })
+    #[pyo3(signature = (root_ca_cert, domain, roots=None))]
+    pub fn without_client_auth(
+        root_ca_cert: Option<PathBuf>,
+        domain: String,
+        roots: Option<Vec<TrustRoots>>,
+    ) -> PyResult<Self> {
+        let ca_certs = root_ca_cert
+            .map(|path| TlsAdaptor::read_file(&path, TlsAdaptor::read_certificates))
+            .transpose()?;
+        TlsAdaptor::build(ca_certs, None, roots, domain)
     }
-}
-impl From<TlsAdaptor> for RuTlsAdaptor {
-    fn from(adaptor: TlsAdaptor) -> Self {
-        Arc::unwrap_or_clone(adaptor.inner)
+
+    /// Builds the adaptor from PEM strings or bytes, such as secrets fetched
+    /// from a vault, without writing them to disk.
+    #[staticmethod]
+    #[pyo3(signature = (ca_pem=None, cert_pem=None, key_pem=None, *, domain, roots=None))]
+    fn from_pem(
+        ca_pem: Option<Pem>,
+        cert_pem: Option<Pem>,
+        key_pem: Option<Pem>,
+        domain: String,
+        roots: Option<Vec<TrustRoots>>,
+    ) -> PyResult<Self> {
+        let ca_certs = ca_pem
+            .map(|pem| TlsAdaptor::read_certificates(&mut pem.as_bytes(), "ca_pem"))
+            .transpose()?;
+        let client_auth = match (cert_pem, key_pem) {
+            (Some(cert), Some(key)) => Some((
+                TlsAdaptor::read_certificates(&mut cert.as_bytes(), "cert_pem")?,
+                TlsAdaptor::read_private_key(&mut key.as_bytes(), "key_pem")?,
+            )),
+            (None, None) => None,
+            _ => return Err(PyValueError::new_err("cert_pem and key_pem must be given together")),
+        };
+        TlsAdaptor::build(ca_certs, client_auth, roots, domain)
+    }
+
+    /// Like `from_pem` with DER encoded certificates, each CA and chain
+    /// certificate a separate `bytes`, and a PKCS#8, PKCS#1 or SEC1 key.
+    #[staticmethod]
+    #[pyo3(signature = (ca_der=None, cert_der=None, key_der=None, *, domain, roots=None))]
+    fn from_der(
+        ca_der: Option<Vec<Vec<u8>>>,
+        cert_der: Option<Vec<Vec<u8>>>,
+        key_der: Option<Vec<u8>>,
+        domain: String,
+        roots: Option<Vec<TrustRoots>>,
+    ) -> PyResult<Self> {
+        let certificates = |der: Vec<Vec<u8>>, source: &str| {
+            if der.is_empty() {
+                return Err(PyValueError::new_err(format!("{source} has no certificate")));
+            }
+            Ok(der.into_iter().map(CertificateDer::from).collect::<Vec<_>>())
+        };
+        let ca_certs = ca_der.map(|der| certificates(der, "ca_der")).transpose()?;
+        let client_auth = match (cert_der, key_der) {
+            (Some(cert), Some(key)) => Some((
+                certificates(cert, "cert_der")?,
+                PrivateKeyDer::try_from(key).map_err(|e| PyValueError::new_err(format!("key_der: {e}")))?,
+            )),
+            (None, None) => None,
+            _ => return Err(PyValueError::new_err("cert_der and key_der must be given together")),
+        };
+        TlsAdaptor::build(ca_certs, client_auth, roots, domain)
     }
 }
 
@@ -345,11 +745,15 @@ pub struct QoSConfig {
     pub sub_prefetch: Option<u16>,
     pub rpc_server_prefetch: Option<u16>,
     pub rpc_client_prefetch: Option<u16>,
+    /// Receives RPC replies through RabbitMQ's `amq.rabbitmq.reply-to`
+    /// instead of a declared reply queue.
+    pub rpc_direct_reply_to: bool,
 }
 #[pymethods]
 impl QoSConfig {
     #[new]
-    #[pyo3(signature = (pub_confirm=true, rpc_client_confirm=true, rpc_server_confirm=false, sub_auto_ack=false, rpc_server_auto_ack=false, rpc_client_auto_ack=false, sub_prefetch=None, rpc_server_prefetch=None, rpc_client_prefetch=None))]
+    #[pyo3(signature = (pub_confirm=true, rpc_client_confirm=true, rpc_server_confirm=false, sub_auto_ack=false, rpc_server_auto_ack=false, rpc_client_auto_ack=false, sub_prefetch=None, rpc_server_prefetch=None, rpc_client_prefetch=None, rpc_direct_reply_to=false))]
+    #[allow(clippy::too_many_arguments)]
     fn new(
         pub_confirm: bool,
         rpc_client_confirm: bool,
@@ -360,6 +764,7 @@ impl QoSConfig {
         sub_prefetch: Option<u16>,
         rpc_server_prefetch: Option<u16>,
         rpc_client_prefetch: Option<u16>,
+        rpc_direct_reply_to: bool,
     ) -> Self {
         Self {
             pub_confirm,
@@ -371,11 +776,33 @@ impl QoSConfig {
             sub_prefetch,
             rpc_server_prefetch,
             rpc_client_prefetch,
+            rpc_direct_reply_to,
         }
     }
 
+    /// Reads `{prefix}QOS_PUB_CONFIRM` and the like; unset fields keep their
+    /// defaults.
     #[staticmethod]
-    pub fn default() -> Self {
+    #[pyo3(signature = (prefix="AMQP_"))]
+    fn from_env(prefix: &str) -> PyResult<Self> {
+        Settings::from_env(prefix).qos_config()
+    }
+
+    /// Reads the `qos` table of a TOML, YAML or JSON config file.
+    #[staticmethod]
+    fn from_file(py: Python<'_>, path: PathBuf) -> PyResult<Self> {
+        Settings::from_file(py, &path)?.qos_config()
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "default")]
+    fn py_default() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for QoSConfig {
+    fn default() -> Self {
         Self {
             pub_confirm: true,
             rpc_client_confirm: true,
@@ -386,21 +813,7 @@ impl QoSConfig {
             sub_prefetch: None,
             rpc_server_prefetch: None,
             rpc_client_prefetch: None,
-        }
-    }
-}
-impl From<QoSConfig> for RuQoSConfig {
-    fn from(config: QoSConfig) -> Self {
-        Self {
-            pub_confirm: config.pub_confirm,
-            rpc_client_confirm: config.rpc_client_confirm,
-            rpc_server_confirm: config.rpc_server_confirm,
-            sub_auto_ack: config.sub_auto_ack,
-            rpc_server_auto_ack: config.rpc_server_auto_ack,
-            rpc_client_auto_ack: config.rpc_client_auto_ack,
-            sub_prefetch: config.sub_prefetch,
-            rpc_server_prefetch: config.rpc_server_prefetch,
-            rpc_client_prefetch: config.rpc_client_prefetch,
+            rpc_direct_reply_to: false,
         }
     }
 }
@@ -410,67 +823,240 @@ pub enum Payload<'py> {
     Bytes(Bound<'py, PyBytes>),
     Str(Bound<'py, PyString>),
 }
+
+fn field_table_arg(arguments: Option<Bound<'_, PyDict>>) -> PyResult<FieldTable> {
+    arguments
+        .map(|a| field_table::from_py(&a))
+        .transpose()
+        .map(Option::unwrap_or_default)
+}
+
+type PyFuture = Pin<Box<dyn Future<Output = PyResult<Py<PyAny>>> + Send>>;
+
+/// Adapts a Python handler to a consumer `Handler`. Coroutine functions are
+/// awaited on the subscribing event loop; plain functions are called on the
+/// loop's thread, or on its default executor when `in_executor` is set, so a
+/// slow handler does not stall the loop. Neither runs on a tokio worker.
+///
+/// `reply` turns the value the handler returned into the RPC reply, if any;
+/// an async generator streams one reply chunk per item it yields.
+fn python_handler(
+    handler: Py<PyAny>,
+    locals: pyo3_async_runtimes::TaskLocals,
+    in_executor: bool,
+    reply: fn(Python<'_>, Py<PyAny>) -> Result<Option<Message>, HandlerError>,
+) -> PyResult<Handler> {
+    // A coroutine function only creates its coroutine when called, so running
+    // it in the executor would gain nothing.
+    let is_coroutine_function = Python::attach(|py| -> PyResult<bool> {
+        py.import("inspect")?
+            .call_method1("iscoroutinefunction", (handler.bind(py),))?
+            .is_truthy()
+    })?;
+    let in_executor = in_executor && !is_coroutine_function;
+    let handler = Arc::new(handler);
+    Ok(Arc::new(move |message: Message| -> HandlerFuture {
+        let handler_clone = handler.clone();
+        let locals_clone = locals.clone();
+        Box::pin(async move {
+            pyo3_async_runtimes::tokio::scope(locals_clone.clone(), async move {
+                let future_result = Python::attach(|py| -> PyResult<PyFuture> {
+                    let bound_handler = handler_clone.bind(py);
+                    if in_executor {
+                        let pending = locals_clone.event_loop(py).call_method1(
+                            "run_in_executor",
+                            (py.None(), bound_handler, message),
+                        )?;
+                        return Ok(Box::pin(pyo3_async_runtimes::tokio::into_future(pending)?));
+                    }
+                    if !is_coroutine_function {
+                        return call_soon(py, &locals_clone, Arc::clone(&handler_clone), message);
+                    }
+                    let result = bound_handler.call1((message,))?;
+                    if result.hasattr(intern!(py, "__await__"))? {
+                        Ok(Box::pin(pyo3_async_runtimes::tokio::into_future(result)?))
+                    } else {
+                        Ok(Box::pin(std::future::ready(Ok(result.unbind()))))
+                    }
+                });
+                match future_result {
+                    Ok(py_future) => match py_future.await {
+                        Ok(result) => Python::attach(|py| -> Result<Option<Reply>, HandlerError> {
+                            if result.bind(py).hasattr(intern!(py, "__anext__"))? {
+                                return Ok(Some(stream_reply(result, locals_clone, reply)));
+                            }
+                            Ok(reply(py, result)?.map(|message| Reply::Message(Box::new(message))))
+                        }),
+                        Err(e) => Err(RemoteError::from(e).into()),
+                    },
+                    Err(e) => Err(RemoteError::from(e).into()),
+                }
+            })
+            .await
+        })
+    }))
+}
+
+/// Calls a plain function handler on the thread of its event loop, the way
+/// asyncio runs callbacks. An awaitable it returns is awaited in turn.
+fn call_soon(
+    py: Python<'_>,
+    locals: &pyo3_async_runtimes::TaskLocals,
+    handler: Arc<Py<PyAny>>,
+    message: Message,
+) -> PyResult<PyFuture> {
+    let (tx, rx) = tokio::sync::oneshot::channel();
+    let call = std::sync::Mutex::new(Some((message, tx)));
+    let callback = PyCFunction::new_closure(py, None, None, move |args, _kwargs| {
+        if let Some((message, tx)) = call.lock().unwrap().take() {
+            let _ = tx.send(handler.call1(args.py(), (message,)));
+        }
+    })?;
+    locals
+        .event_loop(py)
+        .call_method1(intern!(py, "call_soon_threadsafe"), (callback,))?;
+    Ok(Box::pin(async move {
+        let result = rx.await.map_err(|_| {
+            pyo3::exceptions::PyRuntimeError::new_err("the event loop closed before the handler ran")
+        })??;
+        let awaitable = Python::attach(|py| -> PyResult<Option<_>> {
+            let result = result.bind(py);
+            if result.hasattr(intern!(py, "__await__"))? {
+                return Ok(Some(pyo3_async_runtimes::tokio::into_future(result.clone())?));
+            }
+            Ok(None)
+        })?;
+        match awaitable {
+            Some(awaitable) => awaitable.await,
+            None => Ok(result),
+        }
+    }))
+}
+
+/// Iterates an async generator returned by an RPC handler on its event loop,
+/// turning each item into a reply chunk with `reply`. Iteration waits for
+/// each chunk to be taken, and once the chunks are no longer wanted the
+/// generator is closed at its pending `yield`, so its cleanup runs.
+fn stream_reply(
+    generator: Py<PyAny>,
+    locals: pyo3_async_runtimes::TaskLocals,
+    reply: fn(Python<'_>, Py<PyAny>) -> Result<Option<Message>, HandlerError>,
+) -> Reply {
+    let (tx, rx) = tokio::sync::mpsc::channel(1);
+    tokio::spawn(pyo3_async_runtimes::tokio::scope(locals, async move {
+        let call = |method: &'static str| {
+            Python::attach(|py| pyo3_async_runtimes::tokio::into_future(generator.bind(py).call_method0(method)?))
+        };
+        loop {
+            if tx.is_closed() {
+                if let Ok(close) = call("aclose") {
+                    let _ = close.await;
+                }
+                return;
+            }
+            let item = match call("__anext__") {
+                Ok(next) => next.await,
+                Err(e) => Err(e),
+            };
+            let chunk = match item {
+                Ok(item) => match Python::attach(|py| reply(py, item)) {
+                    Ok(Some(message)) => Ok(message),
+                    Ok(None) => continue,
+                    Err(e) => Err(e),
+                },
+                Err(e) if Python::attach(|py| e.is_instance_of::<PyStopAsyncIteration>(py)) => return,
+                Err(e) => Err(RemoteError::from(e).into()),
+            };
+            let failed = chunk.is_err();
+            if tx.send(chunk).await.is_ok() && failed {
+                return;
+            }
+        }
+    }));
+    Reply::Stream(rx)
+}
+
+pub(crate) fn no_reply(_py: Python<'_>, _result: Py<PyAny>) -> Result<Option<Message>, HandlerError> {
+    Ok(None)
+}
+
+pub(crate) fn rpc_reply(py: Python<'_>, result: Py<PyAny>) -> Result<Option<Message>, HandlerError> {
+    if let Ok(message) = result.extract::<Message>(py) {
+        return Ok(Some(message));
+    }
+    match result.cast_bound::<PyBytes>(py) {
+        Ok(bytes) => Ok(Some(Message::from_body(bytes.as_bytes().into(), None))),
+        Err(_) => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "RPC handler must return bytes",
+        )
+        .into()),
+    }
+}
+
+#[derive(FromPyObject)]
+pub enum PublishBody<'py> {
+    Message(PyRef<'py, Message>),
+    Payload(Payload<'py>),
+}
 #[pymethods]
 impl AsyncEventbus {
     #[new]
-    fn new(config: Config, qos_config: QoSConfig) -> Self {
-        let rt = pyo3_async_runtimes::tokio::get_runtime();
-
-        let _guard = rt.enter();
-        Self {
-            eventbus: Arc::new(RuAsyncEventbusRabbitMQ::new(
-                config.into(),
-                qos_config.into(),
-            )),
-        }
+    fn new(config: Config, qos_config: QoSConfig) -> PyResult<Self> {
+        Ok(Self {
+            bus: Bus::new(config, qos_config)?,
+        })
     }
 
-    #[pyo3(signature = (exchange_name, routing_key, body, content_type=Some("application/json"), content_encoding=ContentEncoding::Null, command_timeout=16, delivery_mode=DeliveryMode::Transient, expiration=None))]
+    #[pyo3(signature = (exchange_name, routing_key, body, content_type=Some("application/json"), content_encoding=ContentEncoding::Null, command_timeout=16, delivery_mode=DeliveryMode::Transient, expiration=None, headers=None, message_id=None, correlation_id=None, priority=None, timestamp=None, app_id=None, r#type=None, user_id=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
     fn publish<'py>(
         slf: PyRef<'py, Self>,
         exchange_name: &'py str,
         routing_key: &'py str,
-        body: Payload,
+        body: PublishBody<'py>,
         content_type: Option<&'py str>,
         content_encoding: ContentEncoding,
         command_timeout: Option<u64>,
         delivery_mode: DeliveryMode,
         expiration: Option<u32>,
+        headers: Option<Bound<'py, PyDict>>,
+        message_id: Option<String>,
+        correlation_id: Option<String>,
+        priority: Option<u8>,
+        timestamp: Option<u64>,
+        app_id: Option<String>,
+        r#type: Option<String>,
+        user_id: Option<String>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
     ) -> PyResult<Bound<'py, PyAny>> {
-        let eventbus = Arc::clone(&slf.eventbus);
-        let py = slf.py();
-
-        let exchange_name = exchange_name.to_owned();
-        let routing_key = routing_key.to_owned();
-        let payload_bytes = match body {
-            Payload::Bytes(b) => b.as_bytes().to_vec(),
-            Payload::Str(s) => s.to_str()?.as_bytes().to_vec(),
-        };
-
-        let content_type = content_type.map(|s| s.to_owned());
-        let content_encoding = content_encoding.clone();
-        pyo3_async_runtimes::tokio::future_into_py(py, async move {
-            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
-            match eventbus
-                .publish(
-                    &exchange_name,
-                    &routing_key,
-                    payload_bytes,
-                    content_type.as_deref(),
-                    content_encoding.into(),
-                    command_timeout,
-                    Some(delivery_mode.into()),
-                    expiration,
-                )
-                .await
-            {
-                Ok(res) => Ok(res),
-                Err(e) => return Err(AppError::from(e).into()),
-            }
-        })
+        let request = PublishRequest::new(
+            exchange_name,
+            routing_key,
+            body,
+            content_type,
+            content_encoding,
+            command_timeout,
+            delivery_mode,
+            expiration,
+            headers,
+            message_id,
+            correlation_id,
+            priority,
+            timestamp,
+            app_id,
+            r#type,
+            user_id,
+            exchange_type,
+            exchange_arguments,
+        )?;
+        let bus = slf.bus.clone();
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move { bus.publish(request).await })
     }
 
-    #[pyo3(signature = (exchange_name, routing_key, body, content_type="application/json", content_encoding=ContentEncoding::Null, response_timeout=20_000, connection_timeout=Some(32), delivery_mode=DeliveryMode::Transient, expiration=None))]
+    #[pyo3(signature = (exchange_name, routing_key, body, content_type="application/json", content_encoding=ContentEncoding::Null, response_timeout=20_000, connection_timeout=Some(32), delivery_mode=DeliveryMode::Transient, expiration=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
     fn rpc_client<'py>(
         slf: PyRef<'py, Self>,
         exchange_name: &str,
@@ -482,42 +1068,127 @@ impl AsyncEventbus {
         connection_timeout: Option<u64>,
         delivery_mode: DeliveryMode,
         expiration: Option<u32>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
     ) -> PyResult<Bound<'py, PyAny>> {
-        let eventbus = Arc::clone(&slf.eventbus);
+        let request = RpcRequest::new(
+            exchange_name,
+            routing_key,
+            body,
+            content_type,
+            content_encoding,
+            response_timeout,
+            connection_timeout,
+            delivery_mode,
+            expiration,
+            exchange_type,
+            exchange_arguments,
+        )?;
+        let bus = slf.bus.clone();
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move { bus.rpc_client(request).await })
+    }
 
-        let exchange_name = exchange_name.to_owned();
-        let routing_key = routing_key.to_owned();
-        let payload_bytes = match body {
-            Payload::Bytes(b) => b.as_bytes().to_vec(),
-            Payload::Str(s) => s.to_str()?.as_bytes().to_vec(),
-        };
-        let content_type = content_type.to_owned();
-        let content_encoding = content_encoding.clone();
+    #[pyo3(signature = (exchange_name, routing_key, body, content_type="application/json", content_encoding=ContentEncoding::Null, response_timeout=20_000, connection_timeout=Some(32), delivery_mode=DeliveryMode::Transient, expiration=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn rpc_call<'py>(
+        slf: PyRef<'py, Self>,
+        exchange_name: &str,
+        routing_key: &str,
+        body: Payload<'py>,
+        content_type: &str,
+        content_encoding: ContentEncoding,
+        response_timeout: u32,
+        connection_timeout: Option<u64>,
+        delivery_mode: DeliveryMode,
+        expiration: Option<u32>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = RpcRequest::new(
+            exchange_name,
+            routing_key,
+            body,
+            content_type,
+            content_encoding,
+            response_timeout,
+            connection_timeout,
+            delivery_mode,
+            expiration,
+            exchange_type,
+            exchange_arguments,
+        )?;
+        let bus = slf.bus.clone();
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move { bus.rpc_call(request).await })
+    }
 
-        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
-            let conn_timeout = connection_timeout.map(std::time::Duration::from_secs);
-            let response = match eventbus
-                .rpc_client(
-                    &exchange_name,
-                    &routing_key,
-                    payload_bytes,
-                    &content_type,
-                    content_encoding.into(),
-                    response_timeout,
-                    conn_timeout,
-                    Some(delivery_mode.into()),
-                    expiration,
-                )
-                .await
-            {
-                Ok(res) => Ok(res),
-                Err(e) => Err(AppError::from(e).into()),
-            };
-            response
-        })
+    #[pyo3(signature = (exchange_name, routing_key, body, timeout, max_replies=None, content_type="application/json", content_encoding=ContentEncoding::Null, connection_timeout=Some(32), delivery_mode=DeliveryMode::Transient, expiration=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn rpc_gather<'py>(
+        slf: PyRef<'py, Self>,
+        exchange_name: &str,
+        routing_key: &str,
+        body: Payload<'py>,
+        timeout: u32,
+        max_replies: Option<usize>,
+        content_type: &str,
+        content_encoding: ContentEncoding,
+        connection_timeout: Option<u64>,
+        delivery_mode: DeliveryMode,
+        expiration: Option<u32>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let request = RpcRequest::new(
+            exchange_name,
+            routing_key,
+            body,
+            content_type,
+            content_encoding,
+            timeout,
+            connection_timeout,
+            delivery_mode,
+            expiration,
+            exchange_type,
+            exchange_arguments,
+        )?;
+        let bus = slf.bus.clone();
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move { bus.rpc_gather(request, max_replies).await })
     }
 
-    #[pyo3(signature = (exchange_name, routing_key, handler, process_timeout=None, command_timeout=Some(16)))]
+    #[pyo3(signature = (exchange_name, routing_key, body, content_type="application/json", content_encoding=ContentEncoding::Null, chunk_timeout=20_000, connection_timeout=Some(32), delivery_mode=DeliveryMode::Transient, expiration=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn rpc_stream<'py>(
+        &self,
+        exchange_name: &str,
+        routing_key: &str,
+        body: Payload<'py>,
+        content_type: &str,
+        content_encoding: ContentEncoding,
+        chunk_timeout: u32,
+        connection_timeout: Option<u64>,
+        delivery_mode: DeliveryMode,
+        expiration: Option<u32>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<ReplyStream> {
+        let request = RpcRequest::new(
+            exchange_name,
+            routing_key,
+            body,
+            content_type,
+            content_encoding,
+            chunk_timeout,
+            connection_timeout,
+            delivery_mode,
+            expiration,
+            exchange_type,
+            exchange_arguments,
+        )?;
+        Ok(self.bus.rpc_stream(request))
+    }
+
+    #[pyo3(signature = (exchange_name, routing_key, handler, process_timeout=None, command_timeout=Some(16), queue_options=None, exchange_type=None, exchange_arguments=None, run_in_executor=false))]
+    #[allow(clippy::too_many_arguments)]
     fn subscribe<'py>(
         slf: PyRef<'py, Self>,
         exchange_name: &str,
@@ -525,163 +1196,305 @@ impl AsyncEventbus {
         handler: Py<PyAny>,
         process_timeout: Option<u64>,
         command_timeout: Option<u64>,
+        queue_options: Option<QueueOptions>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+        run_in_executor: bool,
     ) -> PyResult<Bound<'py, PyAny>> {
-        let eventbus = Arc::clone(&slf.eventbus);
         let locals = pyo3_async_runtimes::TaskLocals::with_running_loop(slf.py())?;
-        let py = slf.py();
-        let handler = Arc::new(handler);
-        let exchange_name = exchange_name.to_owned();
-        let routing_key = routing_key.to_owned();
+        let handler = python_handler(handler, locals, run_in_executor, no_reply)?;
+        let spec = subscribe_spec(exchange_name, routing_key, queue_options, exchange_type, exchange_arguments)?;
+        let bus = slf.bus.clone();
 
-        pyo3_async_runtimes::tokio::future_into_py(py, async move {
-            let process_timeout = process_timeout.map(std::time::Duration::from_secs);
-            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
-
-            match eventbus
-                .subscribe(
-                    &exchange_name,
-                    &routing_key,
-                    move |body| {
-                        let handler_clone = handler.clone();
-                        let locals_clone = locals.clone();
-                        async move {
-                            pyo3_async_runtimes::tokio::scope(locals_clone, async move {
-                                let future_result = Python::attach(|py| -> PyResult<_> {
-                                    let bound_handler = handler_clone.bind(py);
-                                    let coro = bound_handler.call1((Message::from(body),))?;
-
-                                    pyo3_async_runtimes::tokio::into_future(coro)
-                                });
-                                match future_result {
-                                    Ok(py_future) => match py_future.await {
-                                        Ok(_) => Ok(()),
-                                        Err(e) => Err(Box::new(std::io::Error::new(
-                                            std::io::ErrorKind::Other,
-                                            e.to_string(),
-                                        ))
-                                            as Box<dyn std::error::Error + Send + Sync>),
-                                    },
-                                    Err(e) => Err(Box::new(std::io::Error::new(
-                                        std::io::ErrorKind::Other,
-                                        format!("Failed to execute Python callback: {}", e),
-                                    ))
-                                        as Box<dyn std::error::Error + Send + Sync>),
-                                }
-                            })
-                            .await
-                        }
-                    },
-                    process_timeout,
-                    command_timeout,
-                )
-                .await
-            {
-                Ok(res) => Ok(res),
-                Err(e) => Err(AppError::from(e).into()),
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            match bus.subscribe(spec, handler, process_timeout, command_timeout).await {
+                Ok(state) => Ok(Subscription::new(Arc::clone(&bus.subscriber), state, false)),
+                Err(e) => Err(e.into()),
             }
         })
     }
 
-    #[pyo3(signature = (routing_key, handler, process_timeout=None, command_timeout=None))]
+    #[pyo3(signature = (routing_key, handler, process_timeout=None, command_timeout=None, queue_options=None, exchange_type=None, exchange_arguments=None, run_in_executor=false, include_traceback=false))]
+    #[allow(clippy::too_many_arguments)]
     fn provide_resource<'py>(
         slf: PyRef<'py, Self>,
         routing_key: &str,
         handler: Py<PyAny>,
         process_timeout: Option<u64>,
         command_timeout: Option<u64>,
+        queue_options: Option<QueueOptions>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+        run_in_executor: bool,
+        include_traceback: bool,
     ) -> PyResult<Bound<'py, PyAny>> {
-        let eventbus = Arc::clone(&slf.eventbus);
         let locals = pyo3_async_runtimes::TaskLocals::with_running_loop(slf.py())?;
-        let py = slf.py();
-        let handler = Arc::new(handler);
-        let routing_key = routing_key.to_owned();
+        let handler = python_handler(handler, locals, run_in_executor, rpc_reply)?;
+        let spec = SubscribeSpec {
+            include_traceback,
+            ..subscribe_spec(
+                &slf.bus.rpc_exchange_name,
+                routing_key,
+                queue_options,
+                exchange_type,
+                exchange_arguments,
+            )?
+        };
+        let bus = slf.bus.clone();
 
-        pyo3_async_runtimes::tokio::future_into_py(py, async move {
-            let process_timeout = process_timeout.map(std::time::Duration::from_secs);
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            match bus.provide_resource(spec, handler, process_timeout, command_timeout).await {
+                Ok(state) => Ok(Subscription::new(Arc::clone(&bus.rpc_server), state, false)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    #[pyo3(signature = (exchange_name, routing_key, prefetch=None, command_timeout=Some(16), queue_options=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn consume<'py>(
+        slf: PyRef<'py, Self>,
+        exchange_name: &str,
+        routing_key: &str,
+        prefetch: Option<u16>,
+        command_timeout: Option<u64>,
+        queue_options: Option<QueueOptions>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<MessageStream> {
+        let spec = SubscribeSpec {
+            prefetch,
+            manual_settle: true,
+            ..subscribe_spec(exchange_name, routing_key, queue_options, exchange_type, exchange_arguments)?
+        };
+        Ok(MessageStream::new(
+            Arc::clone(&slf.bus.subscriber),
+            spec,
+            command_timeout.map(std::time::Duration::from_secs),
+        ))
+    }
+
+    #[pyo3(signature = (name, exchange_type=ExchangeKind::Builtin(ExchangeType::Topic), durable=true, auto_delete=false, internal=false, passive=false, arguments=None, command_timeout=Some(16)))]
+    #[allow(clippy::too_many_arguments)]
+    fn declare_exchange<'py>(
+        slf: PyRef<'py, Self>,
+        name: &str,
+        exchange_type: ExchangeKind,
+        durable: bool,
+        auto_delete: bool,
+        internal: bool,
+        passive: bool,
+        arguments: Option<Bound<'py, PyDict>>,
+        command_timeout: Option<u64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let topology = Arc::clone(&slf.bus.topology);
+        let spec = ExchangeSpec {
+            name: name.to_owned(),
+            exchange_type: exchange_type.into_string(),
+            durable,
+            auto_delete,
+            internal,
+            passive,
+            arguments: field_table_arg(arguments)?,
+        };
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
             let command_timeout = command_timeout.map(std::time::Duration::from_secs);
+            topology.declare_exchange(spec, command_timeout).await.map_err(Into::into)
+        })
+    }
 
-            match eventbus
-                .provide_resource(
-                    &routing_key,
-                    move |body| {
-                        let handler_clone = handler.clone();
-                        let locals_clone = locals.clone();
-                        async move {
-                            pyo3_async_runtimes::tokio::scope(locals_clone, async move {
-                                let py_future_result = Python::attach(|py| -> PyResult<_> {
-                                    let bound_handler = handler_clone.bind(py);
-                                    let coro = bound_handler.call1((Message::from(body),))?;
-
-                                    // Now into_future will successfully find the asyncio loop!
-                                    pyo3_async_runtimes::tokio::into_future(coro)
-                                });
-                                match py_future_result {
-                                    Ok(py_future) => match py_future.await {
-                                        Ok(result) => {
-                                            Python::attach(|py| {
-                                                if let Ok(message) = result.extract::<Message>(py) {
-                                                    return Ok(RuMessage::from(message));
-                                                }
-                                            match result.cast_bound::<PyBytes>(py) {
-                                                Ok(bytes) => Ok( RuMessage { body: bytes.as_bytes().into(), content_type: None }),
-                                                Err(_) => Err(Box::new(std::io::Error::new(
-                                                    std::io::ErrorKind::InvalidData,
-                                                    "RPC handler must return bytes",
-                                                ))
-                                                    as Box<dyn std::error::Error + Send + Sync>),
-                                            }
-                                        })},
-                                        Err(e) => Err(Box::new(std::io::Error::new(
-                                            std::io::ErrorKind::Other,
-                                            e.to_string(),
-                                        ))
-                                            as Box<dyn std::error::Error + Send + Sync>),
-                                    },
-                                    Err(e) => Err(Box::new(std::io::Error::new(
-                                        std::io::ErrorKind::Other,
-                                        format!("Failed to execute Python callback: {}", e),
-                                    ))
-                                        as Box<dyn std::error::Error + Send + Sync>),
-                                }
-                            })
-                            .await
-                        }
-                    },
-                    process_timeout,
-                    command_timeout,
-                )
+    #[pyo3(signature = (name="", durable=true, exclusive=false, auto_delete=false, passive=false, arguments=None, command_timeout=Some(16)))]
+    #[allow(clippy::too_many_arguments)]
+    fn declare_queue<'py>(
+        slf: PyRef<'py, Self>,
+        name: &str,
+        durable: bool,
+        exclusive: bool,
+        auto_delete: bool,
+        passive: bool,
+        arguments: Option<Bound<'py, PyDict>>,
+        command_timeout: Option<u64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let topology = Arc::clone(&slf.bus.topology);
+        let spec = QueueSpec {
+            name: name.to_owned(),
+            durable,
+            exclusive,
+            auto_delete,
+            passive,
+            arguments: field_table_arg(arguments)?,
+        };
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
+            topology.declare_queue(spec, command_timeout).await.map_err(Into::into)
+        })
+    }
+
+    #[pyo3(signature = (queue, exchange, routing_key="", arguments=None, command_timeout=Some(16)))]
+    fn bind_queue<'py>(
+        slf: PyRef<'py, Self>,
+        queue: &str,
+        exchange: &str,
+        routing_key: &str,
+        arguments: Option<Bound<'py, PyDict>>,
+        command_timeout: Option<u64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let topology = Arc::clone(&slf.bus.topology);
+        let (queue, exchange, routing_key) = (queue.to_owned(), exchange.to_owned(), routing_key.to_owned());
+        let arguments = field_table_arg(arguments)?;
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
+            topology
+                .bind_queue(&queue, &exchange, &routing_key, arguments, command_timeout)
                 .await
-            {
-                Ok(res) => Ok(res),
-                Err(e) => Err(AppError::from(e).into()),
-            }
+                .map_err(Into::into)
         })
     }
-    fn dispose(slf: PyRef<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
-        let eventbus = Arc::clone(&slf.eventbus); // Clone the Arc for the async move
-        let py = slf.py();
-
-        pyo3_async_runtimes::tokio::future_into_py(py, async move {
-            eventbus.dispose().await.map_err(|_| {
-                AppError {
-                    description: None,
-                    message: None,
-                    error_type: amqp_client_rust::errors::AppErrorType::UnexpectedResultError,
-                }
-                .into()
-            })
+
+    #[pyo3(signature = (queue, exchange, routing_key="", arguments=None, command_timeout=Some(16)))]
+    fn unbind_queue<'py>(
+        slf: PyRef<'py, Self>,
+        queue: &str,
+        exchange: &str,
+        routing_key: &str,
+        arguments: Option<Bound<'py, PyDict>>,
+        command_timeout: Option<u64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let topology = Arc::clone(&slf.bus.topology);
+        let (queue, exchange, routing_key) = (queue.to_owned(), exchange.to_owned(), routing_key.to_owned());
+        let arguments = field_table_arg(arguments)?;
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
+            topology
+                .unbind_queue(&queue, &exchange, &routing_key, arguments, command_timeout)
+                .await
+                .map_err(Into::into)
         })
     }
+
+    #[pyo3(signature = (destination, source, routing_key="", arguments=None, command_timeout=Some(16)))]
+    fn bind_exchange<'py>(
+        slf: PyRef<'py, Self>,
+        destination: &str,
+        source: &str,
+        routing_key: &str,
+        arguments: Option<Bound<'py, PyDict>>,
+        command_timeout: Option<u64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let topology = Arc::clone(&slf.bus.topology);
+        let (destination, source, routing_key) = (destination.to_owned(), source.to_owned(), routing_key.to_owned());
+        let arguments = field_table_arg(arguments)?;
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
+            topology
+                .bind_exchange(&destination, &source, &routing_key, arguments, command_timeout)
+                .await
+                .map_err(Into::into)
+        })
+    }
+
+    #[pyo3(signature = (destination, source, routing_key="", arguments=None, command_timeout=Some(16)))]
+    fn unbind_exchange<'py>(
+        slf: PyRef<'py, Self>,
+        destination: &str,
+        source: &str,
+        routing_key: &str,
+        arguments: Option<Bound<'py, PyDict>>,
+        command_timeout: Option<u64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let topology = Arc::clone(&slf.bus.topology);
+        let (destination, source, routing_key) = (destination.to_owned(), source.to_owned(), routing_key.to_owned());
+        let arguments = field_table_arg(arguments)?;
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
+            topology
+                .unbind_exchange(&destination, &source, &routing_key, arguments, command_timeout)
+                .await
+                .map_err(Into::into)
+        })
+    }
+
+    #[pyo3(signature = (name, if_unused=false, if_empty=false, command_timeout=Some(16)))]
+    fn delete_queue<'py>(
+        slf: PyRef<'py, Self>,
+        name: &str,
+        if_unused: bool,
+        if_empty: bool,
+        command_timeout: Option<u64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let topology = Arc::clone(&slf.bus.topology);
+        let name = name.to_owned();
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
+            topology
+                .delete_queue(&name, if_unused, if_empty, command_timeout)
+                .await
+                .map_err(Into::into)
+        })
+    }
+
+    #[pyo3(signature = (name, if_unused=false, command_timeout=Some(16)))]
+    fn delete_exchange<'py>(
+        slf: PyRef<'py, Self>,
+        name: &str,
+        if_unused: bool,
+        command_timeout: Option<u64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let topology = Arc::clone(&slf.bus.topology);
+        let name = name.to_owned();
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
+            topology
+                .delete_exchange(&name, if_unused, command_timeout)
+                .await
+                .map_err(Into::into)
+        })
+    }
+
+    #[pyo3(signature = (name, command_timeout=Some(16)))]
+    fn purge_queue<'py>(
+        slf: PyRef<'py, Self>,
+        name: &str,
+        command_timeout: Option<u64>,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        let topology = Arc::clone(&slf.bus.topology);
+        let name = name.to_owned();
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            let command_timeout = command_timeout.map(std::time::Duration::from_secs);
+            topology.purge_queue(&name, command_timeout).await.map_err(Into::into)
+        })
+    }
+
+    fn dispose(slf: PyRef<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
+        let bus = slf.bus.clone();
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move { bus.dispose().await })
+    }
 }
 
 #[pymodule]
 fn amqp_rs(m: &Bound<'_, PyModule>) -> PyResult<()> {
     m.add_class::<AsyncEventbus>()?;
+    m.add_class::<SyncEventbus>()?;
     m.add_class::<Config>()?;
     m.add_class::<ConfigOptions>()?;
     m.add_class::<QoSConfig>()?;
     m.add_class::<TlsAdaptor>()?;
+    m.add_class::<AuthMechanism>()?;
+    m.add_class::<HostSelection>()?;
+    m.add_class::<TrustRoots>()?;
+    m.add_class::<CredentialsProvider>()?;
     m.add_class::<ContentEncoding>()?;
+    m.add_class::<DeliveryMode>()?;
     m.add_class::<Message>()?;
+    m.add_class::<DeliveryInfo>()?;
+    m.add_class::<QueueInfo>()?;
+    m.add_class::<Subscription>()?;
+    m.add_class::<MessageStream>()?;
+    m.add_class::<ReplyStream>()?;
+    m.add_class::<QueueOptions>()?;
+    m.add_class::<ExchangeType>()?;
+    m.add_class::<QueueType>()?;
+    m.add_class::<Overflow>()?;
+    exceptions::register(m)?;
     Ok(())
 }
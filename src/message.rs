@@ -0,0 +1,440 @@
+use std::{future::Future, sync::Arc};
+
+use amqp_client_rust::{
+    amqprs::{BasicProperties, Deliver, FieldTable},
+    api::utils::{compress, decompress, ContentEncoding as RuContentEncoding},
+};
+use pyo3::{
+    prelude::*,
+    types::{PyBytes, PyDict},
+};
+
+use crate::{
+    consumer::Acker,
+    exceptions::{AppError, ErrorType},
+    field_table, DeliveryMode, Payload,
+};
+
+/// Compresses a body the way the inner crate does when `content_encoding` is
+/// one it writes. Other encodings are passed through, the body is expected to
+/// be encoded by the application already.
+pub(crate) fn compress_body(body: Vec<u8>, content_encoding: Option<&str>) -> Result<Vec<u8>, AppError> {
+    match content_encoding.and_then(RuContentEncoding::from_str) {
+        Some(RuContentEncoding::None) | None => Ok(body),
+        Some(encoding) => compress(body, encoding).map_err(AppError::from),
+    }
+}
+
+/// Decompresses a body whose `content_encoding` is one the inner crate
+/// writes. Other encodings are passed through for the application to handle.
+pub(crate) fn decompress_body(content: Vec<u8>, content_encoding: Option<&str>) -> Result<Vec<u8>, AppError> {
+    match content_encoding.and_then(RuContentEncoding::from_str) {
+        Some(RuContentEncoding::None) | None => Ok(content),
+        Some(_) => decompress(content, content_encoding).map_err(AppError::from),
+    }
+}
+
+/// Where a consumed message came from. Only set on messages handed to
+/// subscribe handlers.
+#[pyclass(skip_from_py_object, get_all)]
+#[derive(Debug, Clone)]
+pub struct DeliveryInfo {
+    pub exchange: String,
+    pub routing_key: String,
+    pub redelivered: bool,
+    pub delivery_tag: u64,
+    pub consumer_tag: String,
+}
+
+impl From<&Deliver> for DeliveryInfo {
+    fn from(deliver: &Deliver) -> Self {
+        Self {
+            exchange: deliver.exchange().to_owned(),
+            routing_key: deliver.routing_key().to_owned(),
+            redelivered: deliver.redelivered(),
+            delivery_tag: deliver.delivery_tag(),
+            consumer_tag: deliver.consumer_tag().to_owned(),
+        }
+    }
+}
+
+#[pyclass(from_py_object)]
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub(crate) body: Arc<[u8]>,
+    pub(crate) content_type: Option<String>,
+    pub(crate) content_encoding: Option<String>,
+    pub(crate) headers: Option<FieldTable>,
+    pub(crate) delivery_mode: Option<DeliveryMode>,
+    pub(crate) priority: Option<u8>,
+    pub(crate) correlation_id: Option<String>,
+    pub(crate) reply_to: Option<String>,
+    pub(crate) expiration: Option<String>,
+    pub(crate) message_id: Option<String>,
+    pub(crate) timestamp: Option<u64>,
+    pub(crate) message_type: Option<String>,
+    pub(crate) user_id: Option<String>,
+    pub(crate) app_id: Option<String>,
+    pub(crate) delivery_info: Option<DeliveryInfo>,
+    pub(crate) acker: Option<Acker>,
+}
+
+impl Message {
+    pub(crate) fn from_body(body: Arc<[u8]>, content_type: Option<String>) -> Self {
+        Self {
+            body,
+            content_type,
+            content_encoding: None,
+            headers: None,
+            delivery_mode: None,
+            priority: None,
+            correlation_id: None,
+            reply_to: None,
+            expiration: None,
+            message_id: None,
+            timestamp: None,
+            message_type: None,
+            user_id: None,
+            app_id: None,
+            delivery_info: None,
+            acker: None,
+        }
+    }
+
+    /// Builds a message from a consumed delivery, decoding the body according
+    /// to its `content_encoding`.
+    pub(crate) fn from_delivery(
+        deliver: &Deliver,
+        properties: &BasicProperties,
+        content: Vec<u8>,
+    ) -> Result<Self, AppError> {
+        let body = decompress_body(content, properties.content_encoding().map(String::as_str))?;
+        Ok(Self {
+            delivery_info: Some(DeliveryInfo::from(deliver)),
+            ..Self::from_properties(body.into(), properties)
+        })
+    }
+
+    /// Builds a message carrying every property in `properties`; the inverse
+    /// of `properties`.
+    fn from_properties(body: Arc<[u8]>, properties: &BasicProperties) -> Self {
+        Self {
+            body,
+            content_type: properties.content_type().cloned(),
+            content_encoding: properties.content_encoding().cloned(),
+            headers: properties.headers().cloned(),
+            delivery_mode: properties.delivery_mode().and_then(|mode| match mode {
+                1 => Some(DeliveryMode::Transient),
+                2 => Some(DeliveryMode::Persistent),
+                _ => None,
+            }),
+            priority: properties.priority(),
+            correlation_id: properties.correlation_id().cloned(),
+            reply_to: properties.reply_to().cloned(),
+            expiration: properties.expiration().cloned(),
+            message_id: properties.message_id().cloned(),
+            timestamp: properties.timestamp(),
+            message_type: properties.message_type().cloned(),
+            user_id: properties.user_id().cloned(),
+            app_id: properties.app_id().cloned(),
+            delivery_info: None,
+            acker: None,
+        }
+    }
+
+    /// The body to publish, compressed according to `content_encoding`. The
+    /// body of a consumed message is decompressed, so republishing it encodes
+    /// it again.
+    pub(crate) fn wire_body(&self) -> Result<Vec<u8>, AppError> {
+        compress_body(self.body.to_vec(), self.content_encoding.as_deref())
+    }
+
+    /// AMQP properties to publish this message with.
+    pub(crate) fn properties(&self) -> BasicProperties {
+        let mut properties = BasicProperties::default();
+        if let Some(v) = &self.content_type {
+            properties.with_content_type(v);
+        }
+        if let Some(v) = &self.content_encoding {
+            properties.with_content_encoding(v);
+        }
+        if let Some(v) = &self.headers {
+            properties.with_headers(v.clone());
+        }
+        if let Some(v) = &self.delivery_mode {
+            properties.with_delivery_mode(v.clone() as u8);
+        }
+        if let Some(v) = self.priority {
+            properties.with_priority(v);
+        }
+        if let Some(v) = &self.correlation_id {
+            properties.with_correlation_id(v);
+        }
+        if let Some(v) = &self.reply_to {
+            properties.with_reply_to(v);
+        }
+        if let Some(v) = &self.expiration {
+            properties.with_expiration(v);
+        }
+        if let Some(v) = &self.message_id {
+            properties.with_message_id(v);
+        }
+        if let Some(v) = self.timestamp {
+            properties.with_timestamp(v);
+        }
+        if let Some(v) = &self.message_type {
+            properties.with_message_type(v);
+        }
+        if let Some(v) = &self.user_id {
+            properties.with_user_id(v);
+        }
+        if let Some(v) = &self.app_id {
+            properties.with_app_id(v);
+        }
+        properties.finish()
+    }
+
+    /// Settles the delivery as an awaitable when an asyncio loop is running.
+    /// Without one, as in `SyncEventbus` handlers on the handler pool, it
+    /// settles on the shared runtime before returning `None`.
+    fn settle<'py>(
+        py: Python<'py>,
+        settle: impl Future<Output = Result<(), AppError>> + Send + 'static,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if pyo3_async_runtimes::get_running_loop(py).is_ok() {
+            return pyo3_async_runtimes::tokio::future_into_py(py, async move { settle.await.map_err(Into::into) });
+        }
+        // A runtime cannot block on another from one of its own threads.
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return Err(pyo3::exceptions::PyRuntimeError::new_err(
+                "messages can only be settled from an asyncio loop or a plain thread",
+            ));
+        }
+        py.detach(|| pyo3_async_runtimes::tokio::get_runtime().block_on(settle))?;
+        Ok(py.None().into_bound(py))
+    }
+
+    fn acker(&self) -> Result<Acker, AppError> {
+        self.acker.clone().ok_or_else(|| {
+            AppError::new(
+                ErrorType::AlreadySettled,
+                "message has no pending delivery: it was auto-acknowledged or not consumed",
+            )
+        })
+    }
+}
+
+#[pymethods]
+impl Message {
+    #[staticmethod]
+    #[pyo3(signature = (body, content_type=None, content_encoding=None, headers=None, delivery_mode=None, priority=None, correlation_id=None, reply_to=None, expiration=None, message_id=None, timestamp=None, r#type=None, user_id=None, app_id=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new<'py>(
+        body: Payload<'py>,
+        content_type: Option<String>,
+        content_encoding: Option<String>,
+        headers: Option<Bound<'py, PyDict>>,
+        delivery_mode: Option<DeliveryMode>,
+        priority: Option<u8>,
+        correlation_id: Option<String>,
+        reply_to: Option<String>,
+        expiration: Option<String>,
+        message_id: Option<String>,
+        timestamp: Option<u64>,
+        r#type: Option<String>,
+        user_id: Option<String>,
+        app_id: Option<String>,
+    ) -> PyResult<Self> {
+        let body: Arc<[u8]> = match body {
+            Payload::Bytes(b) => b.as_bytes().into(),
+            Payload::Str(s) => s.to_str()?.as_bytes().into(),
+        };
+        let headers = headers.map(|h| field_table::from_py(&h)).transpose()?;
+        Ok(Self {
+            body,
+            content_type,
+            content_encoding,
+            headers,
+            delivery_mode,
+            priority,
+            correlation_id,
+            reply_to,
+            expiration,
+            message_id,
+            timestamp,
+            message_type: r#type,
+            user_id,
+            app_id,
+            delivery_info: None,
+            acker: None,
+        })
+    }
+
+    #[getter]
+    fn body<'py>(slf: PyRef<'py, Self>) -> Bound<'py, PyBytes> {
+        PyBytes::new(slf.py(), &slf.body)
+    }
+    #[getter]
+    fn content_type(&self) -> Option<&str> {
+        self.content_type.as_deref()
+    }
+    #[getter]
+    fn content_encoding(&self) -> Option<&str> {
+        self.content_encoding.as_deref()
+    }
+    #[getter]
+    fn headers<'py>(&self, py: Python<'py>) -> PyResult<Option<Bound<'py, PyDict>>> {
+        self.headers
+            .as_ref()
+            .map(|h| field_table::to_py(py, h))
+            .transpose()
+    }
+    #[getter]
+    fn delivery_mode(&self) -> Option<DeliveryMode> {
+        self.delivery_mode.clone()
+    }
+    #[getter]
+    fn priority(&self) -> Option<u8> {
+        self.priority
+    }
+    #[getter]
+    fn correlation_id(&self) -> Option<&str> {
+        self.correlation_id.as_deref()
+    }
+    #[getter]
+    fn reply_to(&self) -> Option<&str> {
+        self.reply_to.as_deref()
+    }
+    #[getter]
+    fn expiration(&self) -> Option<&str> {
+        self.expiration.as_deref()
+    }
+    #[getter]
+    fn message_id(&self) -> Option<&str> {
+        self.message_id.as_deref()
+    }
+    #[getter]
+    fn timestamp(&self) -> Option<u64> {
+        self.timestamp
+    }
+    #[getter]
+    #[pyo3(name = "type")]
+    fn message_type(&self) -> Option<&str> {
+        self.message_type.as_deref()
+    }
+    #[getter]
+    fn user_id(&self) -> Option<&str> {
+        self.user_id.as_deref()
+    }
+    #[getter]
+    fn app_id(&self) -> Option<&str> {
+        self.app_id.as_deref()
+    }
+    #[getter]
+    fn delivery_info(&self) -> Option<DeliveryInfo> {
+        self.delivery_info.clone()
+    }
+
+    #[pyo3(signature = (multiple=false))]
+    fn ack<'py>(slf: PyRef<'py, Self>, multiple: bool) -> PyResult<Bound<'py, PyAny>> {
+        let acker = slf.acker()?;
+        Self::settle(slf.py(), async move { acker.ack(multiple).await })
+    }
+
+    #[pyo3(signature = (requeue=true, multiple=false))]
+    fn nack<'py>(slf: PyRef<'py, Self>, requeue: bool, multiple: bool) -> PyResult<Bound<'py, PyAny>> {
+        let acker = slf.acker()?;
+        Self::settle(slf.py(), async move { acker.nack(requeue, multiple).await })
+    }
+
+    #[pyo3(signature = (requeue=true))]
+    fn reject<'py>(slf: PyRef<'py, Self>, requeue: bool) -> PyResult<Bound<'py, PyAny>> {
+        let acker = slf.acker()?;
+        Self::settle(slf.py(), async move { acker.reject(requeue).await })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ContentEncoding;
+
+    const ENCODINGS: [ContentEncoding; 3] = [ContentEncoding::Zstd, ContentEncoding::Lz4, ContentEncoding::Zlib];
+
+    #[test]
+    fn decompresses_bodies_compressed_by_the_inner_crate() {
+        let body = b"{\"id\": 1, \"name\": \"compressed\"}".repeat(8);
+        for encoding in ENCODINGS {
+            let inner = RuContentEncoding::from(encoding.clone());
+            let compressed = compress(body.clone(), inner).unwrap();
+            assert_eq!(encoding.as_property(), Some(inner.as_str()));
+            assert_eq!(decompress_body(compressed, Some(inner.as_str())).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn inner_crate_decompresses_published_bodies() {
+        let body = b"published".repeat(8);
+        for encoding in ENCODINGS {
+            let compressed = compress_body(body.clone(), encoding.as_property()).unwrap();
+            assert_eq!(decompress(compressed, encoding.as_property()).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn passes_unknown_encodings_through() {
+        assert_eq!(decompress_body(b"raw".to_vec(), Some("utf-8")).unwrap(), b"raw");
+        assert_eq!(decompress_body(b"raw".to_vec(), None).unwrap(), b"raw");
+        assert_eq!(compress_body(b"raw".to_vec(), Some("utf-8")).unwrap(), b"raw");
+        assert_eq!(compress_body(b"raw".to_vec(), None).unwrap(), b"raw");
+    }
+
+    #[test]
+    fn republishes_consumed_bodies_encoded() {
+        let body = b"consumed".repeat(8);
+        for encoding in ENCODINGS {
+            let mut message = Message::from_body(body.clone().into(), None);
+            message.content_encoding = encoding.as_property().map(str::to_owned);
+            let wire = message.wire_body().unwrap();
+            assert_ne!(wire, body);
+            assert_eq!(decompress_body(wire, encoding.as_property()).unwrap(), body);
+        }
+    }
+
+    #[test]
+    fn carries_every_property_through_publish_and_consume() {
+        let mut headers = FieldTable::new();
+        headers.insert(
+            "x-trace".try_into().unwrap(),
+            amqp_client_rust::amqprs::FieldValue::l(7),
+        );
+        let mut message = Message::from_body(b"body".to_vec().into(), Some("application/json".to_owned()));
+        message.content_encoding = Some("utf-8".to_owned());
+        message.headers = Some(headers.clone());
+        message.delivery_mode = Some(DeliveryMode::Persistent);
+        message.priority = Some(5);
+        message.correlation_id = Some("correlation".to_owned());
+        message.reply_to = Some("replies".to_owned());
+        message.expiration = Some("60000".to_owned());
+        message.message_id = Some("id".to_owned());
+        message.timestamp = Some(1_704_164_645);
+        message.message_type = Some("created".to_owned());
+        message.user_id = Some("guest".to_owned());
+        message.app_id = Some("app".to_owned());
+
+        let consumed = Message::from_properties(message.body.clone(), &message.properties());
+        assert_eq!(consumed.content_type.as_deref(), Some("application/json"));
+        assert_eq!(consumed.content_encoding.as_deref(), Some("utf-8"));
+        assert_eq!(consumed.headers, Some(headers));
+        assert!(matches!(consumed.delivery_mode, Some(DeliveryMode::Persistent)));
+        assert_eq!(consumed.priority, Some(5));
+        assert_eq!(consumed.correlation_id.as_deref(), Some("correlation"));
+        assert_eq!(consumed.reply_to.as_deref(), Some("replies"));
+        assert_eq!(consumed.expiration.as_deref(), Some("60000"));
+        assert_eq!(consumed.message_id.as_deref(), Some("id"));
+        assert_eq!(consumed.timestamp, Some(1_704_164_645));
+        assert_eq!(consumed.message_type.as_deref(), Some("created"));
+        assert_eq!(consumed.user_id.as_deref(), Some("guest"));
+        assert_eq!(consumed.app_id.as_deref(), Some("app"));
+    }
+}
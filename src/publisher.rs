@@ -0,0 +1,263 @@
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use amqp_client_rust::amqprs::{
+    callbacks::ChannelCallback,
+    channel::{BasicPublishArguments, Channel, ConfirmSelectArguments},
+    error::Error as AmqprsError,
+    Ack, BasicProperties, Cancel, CloseChannel, FieldTable, Nack, Return,
+};
+use async_trait::async_trait;
+use tokio::sync::oneshot;
+
+use crate::{
+    connection::Connector,
+    exceptions::{self, channel_error, AppError, ErrorType},
+    topology::{ExchangeSpec, Topology},
+};
+
+type Waiters = Arc<Mutex<BTreeMap<u64, oneshot::Sender<bool>>>>;
+
+/// Resolves publisher confirmations of one channel to the publishes waiting
+/// on them. Dropping the callback (channel closed) drops the senders, which
+/// the waiting publishes report as a closed connection.
+struct ConfirmCallback {
+    waiters: Waiters,
+}
+
+impl ConfirmCallback {
+    fn resolve(&self, delivery_tag: u64, multiple: bool, acked: bool) {
+        let mut waiters = self.waiters.lock().unwrap();
+        if multiple {
+            let rest = waiters.split_off(&(delivery_tag + 1));
+            for (_, waiter) in std::mem::replace(&mut *waiters, rest) {
+                let _ = waiter.send(acked);
+            }
+        } else if let Some(waiter) = waiters.remove(&delivery_tag) {
+            let _ = waiter.send(acked);
+        }
+    }
+}
+
+#[async_trait]
+impl ChannelCallback for ConfirmCallback {
+    async fn close(&mut self, channel: &Channel, close: CloseChannel) -> Result<(), AmqprsError> {
+        exceptions::record_close(channel, &close);
+        self.waiters.lock().unwrap().clear();
+        Ok(())
+    }
+    async fn cancel(&mut self, _channel: &Channel, _cancel: Cancel) -> Result<(), AmqprsError> {
+        Ok(())
+    }
+    async fn flow(&mut self, _channel: &Channel, active: bool) -> Result<bool, AmqprsError> {
+        Ok(active)
+    }
+    async fn publish_ack(&mut self, _channel: &Channel, ack: Ack) {
+        self.resolve(ack.delivery_tag(), ack.mutiple(), true);
+    }
+    async fn publish_nack(&mut self, _channel: &Channel, nack: Nack) {
+        self.resolve(nack.delivery_tag(), nack.multiple(), false);
+    }
+    async fn publish_return(
+        &mut self,
+        _channel: &Channel,
+        _ret: Return,
+        _basic_properties: BasicProperties,
+        _content: Vec<u8>,
+    ) {
+    }
+}
+
+struct PublishChannel {
+    channel: Channel,
+    waiters: Waiters,
+    next_tag: u64,
+    /// Name, type and arguments of the exchanges declared since this channel
+    /// opened, so a publish with an exchange type only declares once per
+    /// channel, and again with other arguments.
+    declared: Vec<(String, String, FieldTable)>,
+}
+
+/// Publishes on a dedicated channel, waiting for the broker confirmation when
+/// `confirm` is set. The channel is re-opened after it or the connection
+/// closes.
+pub(crate) struct Publisher {
+    connector: Arc<Connector>,
+    topology: Topology,
+    confirm: bool,
+    channel: tokio::sync::Mutex<Option<PublishChannel>>,
+}
+
+impl Publisher {
+    pub(crate) fn new(connector: Arc<Connector>, confirm: bool) -> Self {
+        Self {
+            topology: Topology::new(Arc::clone(&connector)),
+            connector,
+            confirm,
+            channel: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Declares the exchange a publish targets, unless it was declared since
+    /// the publish channel opened. The declaration goes out on a short-lived
+    /// channel: a refused one closes its channel, which must not be the one
+    /// in-flight confirms wait on.
+    pub(crate) async fn declare_exchange(&self, spec: &ExchangeSpec, timeout: Option<Duration>) -> Result<(), AppError> {
+        let key = (spec.name.clone(), spec.exchange_type.clone(), spec.arguments.clone());
+        let channel = {
+            let mut guard = self.channel.lock().await;
+            if guard.as_ref().is_none_or(|c| !c.channel.is_open()) {
+                *guard = Some(self.open(timeout).await?);
+            }
+            let publish_channel = guard.as_ref().unwrap();
+            if publish_channel.declared.contains(&key) {
+                return Ok(());
+            }
+            publish_channel.channel.clone()
+        };
+        self.topology.declare_exchange(spec.clone(), timeout).await?;
+        let mut guard = self.channel.lock().await;
+        if let Some(publish_channel) = guard
+            .as_mut()
+            .filter(|c| c.channel.is_open() && c.channel.channel_id() == channel.channel_id())
+            && !publish_channel.declared.contains(&key)
+        {
+            publish_channel.declared.push(key);
+        }
+        Ok(())
+    }
+
+    /// The channel publishes currently go out on, opened if needed. It stays
+    /// the same until it or the connection closes.
+    pub(crate) async fn channel(&self, timeout: Option<Duration>) -> Result<Channel, AppError> {
+        let mut guard = self.channel.lock().await;
+        if guard.as_ref().is_none_or(|c| !c.channel.is_open()) {
+            *guard = Some(self.open(timeout).await?);
+        }
+        Ok(guard.as_ref().unwrap().channel.clone())
+    }
+
+    pub(crate) async fn publish(
+        &self,
+        exchange_name: &str,
+        routing_key: &str,
+        body: Vec<u8>,
+        properties: BasicProperties,
+        timeout: Option<Duration>,
+    ) -> Result<(), AppError> {
+        self.publish_inner(None, exchange_name, routing_key, body, properties, timeout)
+            .await
+            .map(|_| ())
+    }
+
+    /// Publishes like `publish`, but only on `channel`, a channel returned by
+    /// `channel`. Returns `false` without publishing when the publish channel
+    /// was re-opened since, instead of publishing on the new one.
+    pub(crate) async fn publish_on(
+        &self,
+        channel: &Channel,
+        exchange_name: &str,
+        routing_key: &str,
+        body: Vec<u8>,
+        properties: BasicProperties,
+        timeout: Option<Duration>,
+    ) -> Result<bool, AppError> {
+        self.publish_inner(Some(channel), exchange_name, routing_key, body, properties, timeout)
+            .await
+    }
+
+    async fn publish_inner(
+        &self,
+        only_on: Option<&Channel>,
+        exchange_name: &str,
+        routing_key: &str,
+        body: Vec<u8>,
+        properties: BasicProperties,
+        timeout: Option<Duration>,
+    ) -> Result<bool, AppError> {
+        let confirmation = {
+            let mut guard = self.channel.lock().await;
+            if let Some(expected) = only_on {
+                // A channel that is still open is still the publish channel,
+                // which is only replaced once it closed.
+                let current = guard.as_ref().is_some_and(|c| {
+                    c.channel.is_open() && c.channel.channel_id() == expected.channel_id()
+                });
+                if !current || !expected.is_open() || !expected.is_connection_open() {
+                    return Ok(false);
+                }
+            } else if guard.as_ref().is_none_or(|c| !c.channel.is_open()) {
+                *guard = Some(self.open(timeout).await?);
+            }
+            let publish_channel = guard.as_mut().unwrap();
+            let confirmation = self.confirm.then(|| {
+                let (tx, rx) = oneshot::channel();
+                publish_channel.next_tag += 1;
+                publish_channel
+                    .waiters
+                    .lock()
+                    .unwrap()
+                    .insert(publish_channel.next_tag, tx);
+                rx
+            });
+            publish_channel
+                .channel
+                .basic_publish(
+                    properties,
+                    body,
+                    BasicPublishArguments::new(exchange_name, routing_key),
+                )
+                .await
+                .map_err(|e| AppError::new(ErrorType::ConnectionClosed, e.to_string()))?;
+            confirmation
+        };
+        let Some(confirmation) = confirmation else {
+            return Ok(true);
+        };
+        let acked = match timeout {
+            Some(timeout) => tokio::time::timeout(timeout, confirmation).await.map_err(|_| {
+                AppError::new(
+                    ErrorType::PublishTimeout,
+                    format!("no publisher confirmation within {timeout:?}"),
+                )
+            })?,
+            None => confirmation.await,
+        }
+        .map_err(|_| {
+            AppError::new(
+                ErrorType::ConnectionClosed,
+                "channel closed before the publish was confirmed",
+            )
+        })?;
+        if !acked {
+            return Err(AppError::new(ErrorType::Nack, "the broker nacked the publish"));
+        }
+        Ok(true)
+    }
+
+    async fn open(&self, timeout: Option<Duration>) -> Result<PublishChannel, AppError> {
+        let channel = self.connector.open_channel(timeout).await?;
+        let waiters = Waiters::default();
+        if self.confirm {
+            channel
+                .register_callback(ConfirmCallback {
+                    waiters: Arc::clone(&waiters),
+                })
+                .await
+                .map_err(channel_error(&channel))?;
+            channel
+                .confirm_select(ConfirmSelectArguments::default())
+                .await
+                .map_err(channel_error(&channel))?;
+        }
+        Ok(PublishChannel {
+            channel,
+            waiters,
+            next_tag: 0,
+            declared: Vec::new(),
+        })
+    }
+}
@@ -0,0 +1,488 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use amqp_client_rust::amqprs::{
+    channel::{BasicAckArguments, BasicConsumeArguments, BasicQosArguments, Channel, ConsumerMessage, QueueDeclareArguments},
+    FieldName, FieldTable, FieldValue, LongStr,
+};
+use pyo3::prelude::*;
+use tokio::{
+    sync::mpsc::{self, UnboundedReceiver},
+    task::JoinHandle,
+};
+
+use crate::{
+    connection::Connector,
+    consumer::HandlerError,
+    exceptions::{channel_error, AppError, ErrorType, RemoteHandlerError},
+    message::Message,
+    publisher::Publisher,
+    QoSConfig,
+};
+
+const ERROR_HEADER: &str = "x-rpc-error";
+const ERROR_TYPE_HEADER: &str = "x-rpc-error-type";
+const ERROR_MESSAGE_HEADER: &str = "x-rpc-error-message";
+const ERROR_TRACEBACK_HEADER: &str = "x-rpc-error-traceback";
+/// The error headers travel in the content header frame, which cannot be
+/// split; these caps keep it well below RabbitMQ's default 128 KiB frame_max.
+const MAX_TRACEBACK_LEN: usize = 16 * 1024;
+const MAX_MESSAGE_LEN: usize = 4 * 1024;
+const MAX_TYPE_LEN: usize = 256;
+/// RabbitMQ pseudo-queue that routes replies straight to the consuming
+/// channel, without a reply queue.
+const DIRECT_REPLY_TO: &str = "amq.rabbitmq.reply-to";
+/// Stream cancels are best effort and must not hold up the caller.
+const CANCEL_TIMEOUT: Duration = Duration::from_secs(4);
+
+/// Exception raised by a provider handler, carried back to the caller in the
+/// headers of an error reply.
+#[derive(Debug, Clone)]
+pub(crate) struct RemoteError {
+    pub exception_type: String,
+    pub message: String,
+    pub traceback: Option<String>,
+}
+
+impl RemoteError {
+    pub(crate) fn from_py(py: Python<'_>, error: &PyErr) -> Self {
+        let value = error.value(py);
+        Self {
+            exception_type: value
+                .get_type()
+                .qualname()
+                .map(|name| name.to_string())
+                .unwrap_or_else(|_| "Exception".to_owned()),
+            message: value.str().map(|s| s.to_string()).unwrap_or_default(),
+            traceback: error.traceback(py).and_then(|tb| tb.format().ok()),
+        }
+    }
+
+    /// Describes any handler failure; errors that did not come from Python,
+    /// such as a process timeout, get a matching Python exception name.
+    pub(crate) fn from_handler_error(error: &HandlerError) -> Self {
+        if let Some(remote) = error.downcast_ref::<RemoteError>() {
+            return remote.clone();
+        }
+        let exception_type = if error.is::<tokio::time::error::Elapsed>() {
+            "TimeoutError"
+        } else {
+            "RuntimeError"
+        };
+        Self {
+            exception_type: exception_type.to_owned(),
+            message: error.to_string(),
+            traceback: None,
+        }
+    }
+
+    /// The error reply. Its body is the `Type: message` text, so callers that
+    /// ignore the headers still see what went wrong.
+    pub(crate) fn to_reply(&self, include_traceback: bool) -> Message {
+        let mut headers = FieldTable::new();
+        let mut insert = |key: &str, value: FieldValue| {
+            headers.insert(FieldName::try_from(key).unwrap(), value);
+        };
+        let string = |value: &str| FieldValue::S(LongStr::try_from(value).unwrap());
+        insert(ERROR_HEADER, FieldValue::t(true));
+        insert(ERROR_TYPE_HEADER, string(head(&self.exception_type, MAX_TYPE_LEN)));
+        insert(ERROR_MESSAGE_HEADER, string(head(&self.message, MAX_MESSAGE_LEN)));
+        if let Some(traceback) = self.traceback.as_deref().filter(|_| include_traceback) {
+            // The innermost frames, at the end, say the most.
+            let start = traceback.len().saturating_sub(MAX_TRACEBACK_LEN);
+            let start = (start..traceback.len())
+                .find(|&i| traceback.is_char_boundary(i))
+                .unwrap_or(traceback.len());
+            insert(ERROR_TRACEBACK_HEADER, string(&traceback[start..]));
+        }
+        let body = format!("{}: {}", head(&self.exception_type, MAX_TYPE_LEN), self.message);
+        let mut reply = Message::from_body(body.into_bytes().into(), Some("text/plain".to_owned()));
+        reply.headers = Some(headers);
+        reply
+    }
+
+    /// Removes the headers reserved for error replies from a reply the
+    /// handler returned, so the caller does not mistake it for one.
+    pub(crate) fn strip_headers(reply: &mut Message) {
+        let Some(headers) = reply.headers.as_mut() else {
+            return;
+        };
+        let reserved: Vec<FieldName> = headers
+            .as_ref()
+            .keys()
+            .filter(|name| name.to_string().starts_with(ERROR_HEADER))
+            .cloned()
+            .collect();
+        for name in &reserved {
+            headers.remove(name);
+        }
+    }
+
+    /// Reads an error reply, `None` for a regular response.
+    pub(crate) fn from_reply(reply: &Message) -> Option<Self> {
+        let headers = reply.headers.as_ref()?.as_ref();
+        let header = |key: &str| headers.get(&FieldName::try_from(key).unwrap());
+        let string = |key: &str| match header(key) {
+            Some(FieldValue::S(value)) => Some(value.to_string()),
+            _ => None,
+        };
+        if !matches!(header(ERROR_HEADER), Some(FieldValue::t(true))) {
+            return None;
+        }
+        let exception_type = string(ERROR_TYPE_HEADER).unwrap_or_else(|| "Exception".to_owned());
+        // The body has the message in full when the header was capped. Both
+        // start with the type capped the same way.
+        let message = std::str::from_utf8(&reply.body)
+            .ok()
+            .and_then(|body| body.strip_prefix(head(&exception_type, MAX_TYPE_LEN))?.strip_prefix(": "))
+            .map(str::to_owned)
+            .or_else(|| string(ERROR_MESSAGE_HEADER))
+            .unwrap_or_default();
+        Some(Self {
+            exception_type,
+            message,
+            traceback: string(ERROR_TRACEBACK_HEADER),
+        })
+    }
+}
+
+/// At most the first `max` bytes of `text`, cut at a character boundary.
+fn head(text: &str, max: usize) -> &str {
+    let end = (0..=max.min(text.len()))
+        .rev()
+        .find(|&i| text.is_char_boundary(i))
+        .unwrap_or(0);
+    &text[..end]
+}
+
+impl fmt::Display for RemoteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.exception_type, self.message)
+    }
+}
+
+impl std::error::Error for RemoteError {}
+
+impl From<PyErr> for RemoteError {
+    fn from(error: PyErr) -> Self {
+        Python::attach(|py| Self::from_py(py, &error))
+    }
+}
+
+impl From<RemoteError> for PyErr {
+    fn from(error: RemoteError) -> Self {
+        let err = RemoteHandlerError::new_err(error.to_string());
+        Python::attach(|py| {
+            let value = err.value(py);
+            let _ = value.setattr("message", &error.message);
+            let _ = value.setattr("description", None::<String>);
+            let _ = value.setattr("error_type", "RemoteHandler");
+            let _ = value.setattr("exception_type", &error.exception_type);
+            let _ = value.setattr("traceback", &error.traceback);
+        });
+        err
+    }
+}
+
+/// Requests waiting for replies by correlation id. A request can receive
+/// several replies when it reached more than one provider.
+type Waiters = Arc<Mutex<HashMap<String, mpsc::UnboundedSender<Message>>>>;
+
+/// Exclusive, server-named queue the replies to this client's requests are
+/// consumed from, or the direct reply-to pseudo-queue.
+struct ReplyQueue {
+    channel: Channel,
+    queue_name: String,
+    waiters: Waiters,
+    task: JoinHandle<()>,
+}
+
+/// Removes the waiter of a request that stopped waiting, whether it timed
+/// out, failed to publish or the awaiting task was cancelled.
+pub(crate) struct PendingReply {
+    waiters: Waiters,
+    correlation_id: String,
+}
+
+impl Drop for PendingReply {
+    fn drop(&mut self) {
+        self.waiters.lock().unwrap().remove(&self.correlation_id);
+    }
+}
+
+/// Sends RPC requests and matches the replies to them by correlation id.
+/// The reply queue is declared on first use and again after its channel or
+/// the connection closed.
+pub(crate) struct RpcClient {
+    connector: Arc<Connector>,
+    publisher: Publisher,
+    auto_ack: bool,
+    prefetch: Option<u16>,
+    direct_reply_to: bool,
+    replies: tokio::sync::Mutex<Option<ReplyQueue>>,
+    next_id: AtomicU64,
+}
+
+impl RpcClient {
+    pub(crate) fn new(connector: Arc<Connector>, qos: &QoSConfig) -> Self {
+        Self {
+            publisher: Publisher::new(Arc::clone(&connector), qos.rpc_client_confirm),
+            connector,
+            auto_ack: qos.rpc_client_auto_ack,
+            prefetch: qos.rpc_client_prefetch,
+            direct_reply_to: qos.rpc_direct_reply_to,
+            replies: tokio::sync::Mutex::new(None),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    /// Publishes `message` with this client's reply queue as `reply_to` and
+    /// waits up to `response_timeout` for the reply.
+    pub(crate) async fn call(
+        &self,
+        exchange_name: &str,
+        routing_key: &str,
+        message: Message,
+        response_timeout: Duration,
+        timeout: Option<Duration>,
+    ) -> Result<Message, AppError> {
+        let (mut rx, _pending) = self.send(exchange_name, routing_key, message, timeout).await?;
+        match tokio::time::timeout(response_timeout, rx.recv()).await {
+            Ok(Some(reply)) => Ok(reply),
+            Ok(None) => Err(AppError::new(
+                ErrorType::ConnectionClosed,
+                "the reply channel closed before the response arrived",
+            )),
+            Err(_) => Err(AppError::new(
+                ErrorType::ResponseTimeout,
+                format!("no response within {response_timeout:?}"),
+            )),
+        }
+    }
+
+    /// Publishes `message` once and collects every reply to it that arrives
+    /// before `deadline`, stopping early at `max_replies`.
+    pub(crate) async fn gather(
+        &self,
+        exchange_name: &str,
+        routing_key: &str,
+        message: Message,
+        deadline: Duration,
+        max_replies: Option<usize>,
+        timeout: Option<Duration>,
+    ) -> Result<Vec<Message>, AppError> {
+        let (mut rx, _pending) = self.send(exchange_name, routing_key, message, timeout).await?;
+        let deadline = tokio::time::Instant::now() + deadline;
+        let mut replies = Vec::new();
+        while max_replies.is_none_or(|max| replies.len() < max) {
+            match tokio::time::timeout_at(deadline, rx.recv()).await {
+                Ok(Some(reply)) => replies.push(reply),
+                Ok(None) if replies.is_empty() => {
+                    return Err(AppError::new(
+                        ErrorType::ConnectionClosed,
+                        "the reply channel closed before any response arrived",
+                    ))
+                }
+                Ok(None) | Err(_) => break,
+            }
+        }
+        Ok(replies)
+    }
+
+    /// Publishes `message` under a new correlation id, returning the replies
+    /// to it. They stop arriving once the `PendingReply` is dropped.
+    pub(crate) async fn send(
+        &self,
+        exchange_name: &str,
+        routing_key: &str,
+        mut message: Message,
+        timeout: Option<Duration>,
+    ) -> Result<(UnboundedReceiver<Message>, PendingReply), AppError> {
+        let correlation_id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+        message.correlation_id = Some(correlation_id.clone());
+        for _ in 0..2 {
+            let (channel, queue_name, waiters) = self.reply_queue(timeout).await?;
+            let (tx, rx) = mpsc::unbounded_channel();
+            waiters.lock().unwrap().insert(correlation_id.clone(), tx);
+            let pending = PendingReply {
+                waiters,
+                correlation_id: correlation_id.clone(),
+            };
+            message.reply_to = Some(queue_name);
+            if !self.direct_reply_to {
+                self.publisher
+                    .publish(exchange_name, routing_key, message.wire_body()?, message.properties(), timeout)
+                    .await?;
+                return Ok((rx, pending));
+            }
+            // Direct replies only reach the channel that consumes them, so the
+            // request must not go out on a publish channel re-opened since the
+            // consumer was registered. `reply_queue` consumes on the new one.
+            let published = self
+                .publisher
+                .publish_on(&channel, exchange_name, routing_key, message.wire_body()?, message.properties(), timeout)
+                .await?;
+            if published {
+                return Ok((rx, pending));
+            }
+        }
+        Err(AppError::new(
+            ErrorType::ConnectionClosed,
+            "the publish channel was re-opened while sending the request",
+        ))
+    }
+
+    /// Asks a provider to stop a streamed reply, through the control queue
+    /// named in its chunks.
+    pub(crate) async fn cancel_stream(&self, control_queue: &str, stream_id: &str) -> Result<(), AppError> {
+        let mut message = Message::from_body(Vec::new().into(), None);
+        message.correlation_id = Some(stream_id.to_owned());
+        self.publisher
+            .publish("", control_queue, Vec::new(), message.properties(), Some(CANCEL_TIMEOUT))
+            .await
+    }
+
+    pub(crate) async fn dispose(&self) {
+        if let Some(replies) = self.replies.lock().await.take() {
+            replies.task.abort();
+            replies.waiters.lock().unwrap().clear();
+        }
+    }
+
+    async fn reply_queue(&self, timeout: Option<Duration>) -> Result<(Channel, String, Waiters), AppError> {
+        let mut guard = self.replies.lock().await;
+        if let Some(replies) = guard.as_ref().filter(|r| r.channel.is_open()) {
+            return Ok((
+                replies.channel.clone(),
+                replies.queue_name.clone(),
+                Arc::clone(&replies.waiters),
+            ));
+        }
+        if let Some(stale) = guard.take() {
+            stale.task.abort();
+            // Replies to the stale queue can no longer arrive; dropping the
+            // senders fails the calls still waiting with ConnectionClosedError.
+            stale.waiters.lock().unwrap().clear();
+        }
+        let (channel, queue_name, auto_ack) = if self.direct_reply_to {
+            // Direct replies only reach the channel that published the request,
+            // and must be consumed without acks.
+            let channel = self.publisher.channel(timeout).await?;
+            (channel, DIRECT_REPLY_TO.to_owned(), true)
+        } else {
+            let (channel, queue_name) = self.declare_reply_queue(timeout).await?;
+            (channel, queue_name, self.auto_ack)
+        };
+        let (_, rx) = channel
+            .basic_consume_rx(
+                BasicConsumeArguments::new(&queue_name, "")
+                    .manual_ack(!auto_ack)
+                    .exclusive(!self.direct_reply_to)
+                    .finish(),
+            )
+            .await
+            .map_err(channel_error(&channel))?;
+        let waiters = Waiters::default();
+        let task = tokio::spawn(route_replies(channel.clone(), rx, Arc::clone(&waiters), auto_ack));
+        *guard = Some(ReplyQueue {
+            channel: channel.clone(),
+            queue_name: queue_name.clone(),
+            waiters: Arc::clone(&waiters),
+            task,
+        });
+        Ok((channel, queue_name, waiters))
+    }
+
+    async fn declare_reply_queue(&self, timeout: Option<Duration>) -> Result<(Channel, String), AppError> {
+        let channel = self.connector.open_channel(timeout).await?;
+        let (queue_name, _, _) = channel
+            .queue_declare(QueueDeclareArguments::new("").exclusive(true).auto_delete(true).finish())
+            .await
+            .map_err(channel_error(&channel))?
+            .ok_or_else(|| AppError::new(ErrorType::UnexpectedResult, "the broker did not name the reply queue"))?;
+        if let Some(prefetch) = self.prefetch {
+            channel
+                .basic_qos(BasicQosArguments::new(0, prefetch, false))
+                .await
+                .map_err(channel_error(&channel))?;
+        }
+        Ok((channel, queue_name))
+    }
+}
+
+/// Hands each reply to the request waiting on its correlation id. Replies
+/// nobody waits for any more are dropped.
+async fn route_replies(
+    channel: Channel,
+    mut rx: UnboundedReceiver<ConsumerMessage>,
+    waiters: Waiters,
+    auto_ack: bool,
+) {
+    while let Some(delivery) = rx.recv().await {
+        let (Some(deliver), Some(properties), Some(content)) =
+            (delivery.deliver, delivery.basic_properties, delivery.content)
+        else {
+            continue;
+        };
+        if !auto_ack {
+            let _ = channel
+                .basic_ack(BasicAckArguments::new(deliver.delivery_tag(), false))
+                .await;
+        }
+        let Ok(reply) = Message::from_delivery(&deliver, &properties, content) else {
+            continue;
+        };
+        let Some(correlation_id) = reply.correlation_id.as_deref() else {
+            continue;
+        };
+        if let Some(waiter) = waiters.lock().unwrap().get(correlation_id) {
+            let _ = waiter.send(reply);
+        }
+    }
+    // Dropping the senders fails the requests still waiting on this channel.
+    waiters.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_back_error_replies_with_capped_types() {
+        let error = RemoteError {
+            exception_type: "E".repeat(MAX_TYPE_LEN * 2),
+            message: "x".repeat(MAX_MESSAGE_LEN * 2),
+            traceback: None,
+        };
+        let remote = RemoteError::from_reply(&error.to_reply(false)).unwrap();
+        assert_eq!(remote.exception_type, "E".repeat(MAX_TYPE_LEN));
+        assert_eq!(remote.message, error.message);
+    }
+
+    #[test]
+    fn strips_reserved_headers_from_handler_replies() {
+        let mut reply = RemoteError {
+            exception_type: "ValueError".to_owned(),
+            message: "bad".to_owned(),
+            traceback: Some("Traceback".to_owned()),
+        }
+        .to_reply(true);
+        reply
+            .headers
+            .as_mut()
+            .unwrap()
+            .insert(FieldName::try_from("status").unwrap(), FieldValue::l(200));
+        RemoteError::strip_headers(&mut reply);
+        assert!(RemoteError::from_reply(&reply).is_none());
+        let headers = reply.headers.unwrap();
+        assert_eq!(headers.as_ref().len(), 1);
+        assert_eq!(headers.as_ref().get(&FieldName::try_from("status").unwrap()), Some(&FieldValue::l(200)));
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use pyo3::{
+    exceptions::PyValueError,
+    prelude::*,
+    types::{PyBool, PyDict, PyInt, PyString},
+};
+
+use crate::{
+    credentials::{CredentialsProvider, DEFAULT_POLL_INTERVAL, DEFAULT_REFRESH_BEFORE},
+    uri::{AMQPS_PORT, AMQP_PORT},
+    AuthMechanism, Config, ConfigOptions, HostSelection, QoSConfig, TlsAdaptor, TrustRoots,
+};
+
+/// Every setting `Config` and `QoSConfig` can be loaded from. Sections are
+/// nested tables in files and `_`-joined names in the environment, e.g.
+/// `options.queue_name` is read from `AMQP_OPTIONS_QUEUE_NAME`.
+const KEYS: &[&str] = &[
+    "url",
+    "host",
+    "port",
+    "username",
+    "password",
+    "virtual_host",
+    "heartbeat",
+    "connection_timeout",
+    "auth_mechanism",
+    "connection_name",
+    "credentials_file",
+    "options.queue_name",
+    "options.rpc_exchange_name",
+    "options.rpc_queue_name",
+    "tls.ca_path",
+    "tls.cert_path",
+    "tls.key_path",
+    "tls.domain",
+    "tls.roots",
+    "qos.pub_confirm",
+    "qos.rpc_client_confirm",
+    "qos.rpc_server_confirm",
+    "qos.sub_auto_ack",
+    "qos.rpc_server_auto_ack",
+    "qos.rpc_client_auto_ack",
+    "qos.sub_prefetch",
+    "qos.rpc_server_prefetch",
+    "qos.rpc_client_prefetch",
+    "qos.rpc_direct_reply_to",
+];
+
+enum Value {
+    Text(String),
+    Bool(bool),
+    Int(i64),
+}
+
+enum Origin {
+    Env(String),
+    File(PathBuf),
+}
+
+/// Settings read from the environment or a config file, with errors naming
+/// the variable or key they came from.
+pub(crate) struct Settings {
+    values: HashMap<&'static str, Value>,
+    origin: Origin,
+}
+
+impl Settings {
+    pub(crate) fn from_env(prefix: &str) -> Self {
+        let values = KEYS
+            .iter()
+            .filter_map(|&key| {
+                let value = std::env::var(env_name(prefix, key)).ok()?;
+                Some((key, Value::Text(value)))
+            })
+            .collect();
+        Self {
+            values,
+            origin: Origin::Env(prefix.to_owned()),
+        }
+    }
+
+    /// Reads a TOML, YAML or JSON file, chosen by its extension. Unknown keys
+    /// are rejected so a typo does not silently fall back to a default.
+    pub(crate) fn from_file(py: Python<'_>, path: &Path) -> PyResult<Self> {
+        let text = std::fs::read_to_string(path)?;
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .unwrap_or_default();
+        let document = match extension.as_str() {
+            "json" => py.import("json")?.call_method1("loads", (text,))?,
+            "toml" => py
+                .import("tomllib")
+                .or_else(|_| py.import("tomli"))
+                .map_err(|_| PyValueError::new_err("reading TOML needs Python 3.11 or the tomli package"))?
+                .call_method1("loads", (text,))?,
+            "yaml" | "yml" => py
+                .import("yaml")
+                .map_err(|_| PyValueError::new_err("reading YAML needs the PyYAML package"))?
+                .call_method1("safe_load", (text,))?,
+            _ => {
+                return Err(PyValueError::new_err(format!(
+                    "{}: unsupported config file type, expected .toml, .yaml, .yml or .json",
+                    path.display()
+                )))
+            }
+        };
+        let mut settings = Self {
+            values: HashMap::new(),
+            origin: Origin::File(path.to_owned()),
+        };
+        let document = document
+            .cast_into::<PyDict>()
+            .map_err(|_| PyValueError::new_err(format!("{}: expected a table at the top level", path.display())))?;
+        settings.read_table(&document, "")?;
+        Ok(settings)
+    }
+
+    fn read_table(&mut self, table: &Bound<'_, PyDict>, section: &str) -> PyResult<()> {
+        for (key, value) in table.iter() {
+            let key = format!("{section}{}", key.str()?);
+            if let Ok(table) = value.cast::<PyDict>() {
+                self.read_table(table, &format!("{key}."))?;
+                continue;
+            }
+            let known = KEYS
+                .iter()
+                .find(|&&k| k == key)
+                .ok_or_else(|| self.error(&key, "unknown key".to_owned()))?;
+            let value = if value.is_none() {
+                continue;
+            } else if let Ok(flag) = value.cast::<PyBool>() {
+                Value::Bool(flag.is_true())
+            } else if value.is_instance_of::<PyInt>() {
+                Value::Int(value.extract().map_err(|_| self.error(&key, "number out of range".to_owned()))?)
+            } else if let Ok(text) = value.cast::<PyString>() {
+                Value::Text(text.to_str()?.to_owned())
+            } else {
+                return Err(self.error(&key, format!("unsupported value {}", value.repr()?)));
+            };
+            self.values.insert(*known, value);
+        }
+        Ok(())
+    }
+
+    fn name(&self, key: &str) -> String {
+        match &self.origin {
+            Origin::Env(prefix) => env_name(prefix, key),
+            Origin::File(path) => format!("'{key}' in {}", path.display()),
+        }
+    }
+
+    fn error(&self, key: &str, message: String) -> PyErr {
+        PyValueError::new_err(format!("{}: {message}", self.name(key)))
+    }
+
+    fn invalid(&self, key: &str, expected: &str) -> PyErr {
+        let got = match &self.values[key] {
+            Value::Text(text) => format!("'{text}'"),
+            Value::Bool(flag) => flag.to_string(),
+            Value::Int(number) => number.to_string(),
+        };
+        self.error(key, format!("expected {expected}, got {got}"))
+    }
+
+    fn string(&self, key: &str) -> PyResult<Option<String>> {
+        match self.values.get(key) {
+            Some(Value::Text(text)) => Ok(Some(text.clone())),
+            // A password of digits is still a password.
+            Some(Value::Int(number)) => Ok(Some(number.to_string())),
+            Some(Value::Bool(_)) => Err(self.invalid(key, "a string")),
+            None => Ok(None),
+        }
+    }
+
+    fn required_string(&self, key: &str) -> PyResult<String> {
+        self.string(key)?
+            .ok_or_else(|| self.error(key, "missing required setting".to_owned()))
+    }
+
+    fn number<T: TryFrom<i64>>(&self, key: &str, expected: &str) -> PyResult<Option<T>> {
+        let number = match self.values.get(key) {
+            Some(Value::Int(number)) => Some(*number),
+            Some(Value::Text(text)) => Some(text.trim().parse().map_err(|_| self.invalid(key, expected))?),
+            Some(Value::Bool(_)) => return Err(self.invalid(key, expected)),
+            None => None,
+        };
+        number
+            .map(|number| T::try_from(number).map_err(|_| self.invalid(key, expected)))
+            .transpose()
+    }
+
+    fn flag(&self, key: &str) -> PyResult<Option<bool>> {
+        match self.values.get(key) {
+            Some(Value::Bool(flag)) => Ok(Some(*flag)),
+            Some(Value::Text(text)) => match text.trim().to_ascii_lowercase().as_str() {
+                "true" | "1" | "yes" | "on" => Ok(Some(true)),
+                "false" | "0" | "no" | "off" => Ok(Some(false)),
+                _ => Err(self.invalid(key, "true or false")),
+            },
+            Some(Value::Int(_)) => Err(self.invalid(key, "true or false")),
+            None => Ok(None),
+        }
+    }
+
+    pub(crate) fn config(&self) -> PyResult<Config> {
+        let options = ConfigOptions::new(
+            self.required_string("options.queue_name")?,
+            self.required_string("options.rpc_exchange_name")?,
+            self.required_string("options.rpc_queue_name")?,
+        );
+        let auth_mechanism = self
+            .string("auth_mechanism")?
+            .map(|name| {
+                AuthMechanism::from_name(&name)
+                    .ok_or_else(|| self.invalid("auth_mechanism", "one of PLAIN, AMQPLAIN or EXTERNAL"))
+            })
+            .transpose()?;
+        // EXTERNAL authenticates with the client certificate alone.
+        let credential = |key| match auth_mechanism {
+            Some(AuthMechanism::External) => Ok(self.string(key)?.unwrap_or_default()),
+            _ => self.required_string(key),
+        };
+        let mut config = match self.string("url")? {
+            Some(url) => Config::parse_url(&url, options, None).map_err(|e| self.error("url", message(e)))?,
+            None => Config::new(
+                self.required_string("host")?,
+                AMQP_PORT,
+                Some(credential("username")?),
+                Some(credential("password")?),
+                "/".to_owned(),
+                options,
+                None,
+                None,
+                None,
+                AuthMechanism::Plain,
+                Vec::new(),
+                HostSelection::InOrder,
+                None,
+                None,
+            )?,
+        };
+        if let Some(host) = self.string("host")? {
+            config.host = host;
+        }
+        if let Some(username) = self.string("username")? {
+            config.username = username;
+        }
+        if let Some(password) = self.string("password")? {
+            config.password = password;
+        }
+        if let Some(virtual_host) = self.string("virtual_host")? {
+            config.virtual_host = virtual_host;
+        }
+        config.heartbeat = self.number("heartbeat", "a number of seconds")?.or(config.heartbeat);
+        config.connection_timeout = self
+            .number("connection_timeout", "a number of milliseconds")?
+            .or(config.connection_timeout);
+        if let Some(connection_name) = self.string("connection_name")? {
+            config.connection_name = Some(connection_name);
+        }
+        if let Some(path) = self.string("credentials_file")? {
+            config.credentials_provider = Some(CredentialsProvider::from_file(
+                path.into(),
+                DEFAULT_POLL_INTERVAL,
+                DEFAULT_REFRESH_BEFORE,
+            ));
+        }
+        if let Some(auth_mechanism) = auth_mechanism {
+            config.auth_mechanism = auth_mechanism;
+        }
+        if let Some(tls_adaptor) = self.tls_adaptor(&config.host)? {
+            if !self.values.contains_key("url") {
+                config.port = AMQPS_PORT;
+            }
+            config.tls_adaptor = Some(tls_adaptor);
+        }
+        config.port = self.number("port", "a port number")?.unwrap_or(config.port);
+        if let Some((field, reason)) = config.invalid_field() {
+            // The client certificate is what the tls_adaptor lacks.
+            let key = match field {
+                "tls_adaptor" => "tls.cert_path",
+                field => field,
+            };
+            return Err(self.error(key, reason.to_owned()));
+        }
+        Ok(config)
+    }
+
+    fn tls_adaptor(&self, host: &str) -> PyResult<Option<TlsAdaptor>> {
+        let ca = self.string("tls.ca_path")?.map(PathBuf::from);
+        let cert = self.string("tls.cert_path")?.map(PathBuf::from);
+        let key = self.string("tls.key_path")?.map(PathBuf::from);
+        let domain = self.string("tls.domain")?;
+        let roots = self.roots()?;
+        if ca.is_none() && cert.is_none() && key.is_none() && domain.is_none() && roots.is_none() {
+            return Ok(None);
+        }
+        let domain = domain.unwrap_or_else(|| host.to_owned());
+        // Built step by step so each failure names the setting behind it.
+        let keyed = |key: &'static str| move |e| self.error(key, message(e));
+        let client_auth = match (cert, key) {
+            (Some(cert), Some(key)) => Some((
+                TlsAdaptor::read_file(&cert, TlsAdaptor::read_certificates).map_err(keyed("tls.cert_path"))?,
+                TlsAdaptor::read_file(&key, TlsAdaptor::read_private_key).map_err(keyed("tls.key_path"))?,
+            )),
+            (None, None) => None,
+            (Some(_), None) => return Err(self.error("tls.key_path", "missing, required with tls.cert_path".to_owned())),
+            (None, Some(_)) => return Err(self.error("tls.cert_path", "missing, required with tls.key_path".to_owned())),
+        };
+        let ca_anchors = ca
+            .map(|ca| {
+                let certs = TlsAdaptor::read_file(&ca, TlsAdaptor::read_certificates).map_err(keyed("tls.ca_path"))?;
+                TlsAdaptor::ca_anchors(&certs).map_err(keyed("tls.ca_path"))
+            })
+            .transpose()?;
+        let root_store = TlsAdaptor::build_root_store(ca_anchors, roots).map_err(keyed("tls.roots"))?;
+        TlsAdaptor::with_root_store(root_store, client_auth, domain)
+            .map(Some)
+            .map_err(keyed("tls.cert_path"))
+    }
+
+    /// A comma-separated list such as `native,webpki`.
+    fn roots(&self) -> PyResult<Option<Vec<TrustRoots>>> {
+        self.string("tls.roots")?
+            .map(|roots| {
+                roots
+                    .split(',')
+                    .map(|name| {
+                        TrustRoots::from_name(name).ok_or_else(|| self.invalid("tls.roots", "webpki, native or both"))
+                    })
+                    .collect()
+            })
+            .transpose()
+    }
+
+    pub(crate) fn qos_config(&self) -> PyResult<QoSConfig> {
+        let default = QoSConfig::default();
+        Ok(QoSConfig::new(
+            self.flag("qos.pub_confirm")?.unwrap_or(default.pub_confirm),
+            self.flag("qos.rpc_client_confirm")?.unwrap_or(default.rpc_client_confirm),
+            self.flag("qos.rpc_server_confirm")?.unwrap_or(default.rpc_server_confirm),
+            self.flag("qos.sub_auto_ack")?.unwrap_or(default.sub_auto_ack),
+            self.flag("qos.rpc_server_auto_ack")?.unwrap_or(default.rpc_server_auto_ack),
+            self.flag("qos.rpc_client_auto_ack")?.unwrap_or(default.rpc_client_auto_ack),
+            self.number("qos.sub_prefetch", "a message count")?,
+            self.number("qos.rpc_server_prefetch", "a message count")?,
+            self.number("qos.rpc_client_prefetch", "a message count")?,
+            self.flag("qos.rpc_direct_reply_to")?.unwrap_or(default.rpc_direct_reply_to),
+        ))
+    }
+}
+
+/// The text of an error, without its exception type.
+fn message(error: PyErr) -> String {
+    Python::attach(|py| error.value(py).to_string())
+}
+
+fn env_name(prefix: &str, key: &str) -> String {
+    format!("{prefix}{}", key.replace('.', "_").to_ascii_uppercase())
+}
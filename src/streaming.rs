@@ -0,0 +1,370 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use amqp_client_rust::amqprs::{
+    channel::{BasicConsumeArguments, Channel, ConsumerMessage, QueueDeclareArguments},
+    FieldName, FieldTable, FieldValue, LongStr,
+};
+use pyo3::{exceptions::PyStopAsyncIteration, prelude::*};
+use tokio::sync::{mpsc::UnboundedReceiver, Notify, OnceCell};
+
+use crate::{
+    bus::{Bus, RpcRequest},
+    connection::Connector,
+    exceptions::{channel_error, AppError, ErrorType},
+    message::Message,
+    rpc::{PendingReply, RemoteError},
+};
+
+const STREAM_ID_HEADER: &str = "x-stream-id";
+const STREAM_SEQ_HEADER: &str = "x-stream-seq";
+const STREAM_END_HEADER: &str = "x-stream-end";
+const STREAM_CONTROL_HEADER: &str = "x-stream-control";
+const CONTROL_TIMEOUT: Duration = Duration::from_secs(16);
+
+/// Cancel signals of the streams a provider is sending, by stream id.
+type Cancels = Arc<Mutex<HashMap<String, Arc<Notify>>>>;
+
+/// Exclusive, server-named queue callers send stream cancels to.
+struct ControlQueue {
+    channel: Channel,
+    queue_name: String,
+}
+
+/// Provider side of streamed replies: numbers the streams and stops them when
+/// their caller cancels. The control queue is declared with the first stream.
+pub(crate) struct StreamControl {
+    connector: Arc<Connector>,
+    queue: tokio::sync::Mutex<Option<ControlQueue>>,
+    cancels: Cancels,
+    next_id: AtomicU64,
+}
+
+impl StreamControl {
+    pub(crate) fn new(connector: Arc<Connector>) -> Self {
+        Self {
+            connector,
+            queue: tokio::sync::Mutex::new(None),
+            cancels: Cancels::default(),
+            next_id: AtomicU64::new(0),
+        }
+    }
+
+    pub(crate) async fn register(&self) -> StreamHandle {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed).to_string();
+        let cancelled = Arc::new(Notify::new());
+        self.cancels.lock().unwrap().insert(id.clone(), Arc::clone(&cancelled));
+        // Without a control queue the stream still flows; it just cannot be
+        // cancelled by the caller.
+        let control_queue = self.control_queue().await.ok();
+        StreamHandle {
+            id,
+            control_queue,
+            cancelled,
+            cancels: Arc::clone(&self.cancels),
+        }
+    }
+
+    async fn control_queue(&self) -> Result<String, AppError> {
+        let mut guard = self.queue.lock().await;
+        if let Some(queue) = guard.as_ref().filter(|q| q.channel.is_open()) {
+            return Ok(queue.queue_name.clone());
+        }
+        let channel = self.connector.open_channel(Some(CONTROL_TIMEOUT)).await?;
+        let (queue_name, _, _) = channel
+            .queue_declare(QueueDeclareArguments::new("").exclusive(true).auto_delete(true).finish())
+            .await
+            .map_err(channel_error(&channel))?
+            .ok_or_else(|| AppError::new(ErrorType::UnexpectedResult, "the broker did not name the control queue"))?;
+        let (_, rx) = channel
+            .basic_consume_rx(
+                BasicConsumeArguments::new(&queue_name, "")
+                    .manual_ack(false)
+                    .exclusive(true)
+                    .finish(),
+            )
+            .await
+            .map_err(channel_error(&channel))?;
+        tokio::spawn(route_cancels(rx, Arc::clone(&self.cancels)));
+        *guard = Some(ControlQueue {
+            channel,
+            queue_name: queue_name.clone(),
+        });
+        Ok(queue_name)
+    }
+}
+
+/// Signals the stream named by the correlation id of each cancel message.
+/// Ends when the control channel closes.
+async fn route_cancels(mut rx: UnboundedReceiver<ConsumerMessage>, cancels: Cancels) {
+    while let Some(delivery) = rx.recv().await {
+        let Some(stream_id) = delivery.basic_properties.as_ref().and_then(|p| p.correlation_id()) else {
+            continue;
+        };
+        if let Some(cancelled) = cancels.lock().unwrap().get(stream_id) {
+            cancelled.notify_one();
+        }
+    }
+}
+
+/// One streamed reply being sent. Tags its chunks and stops listening for a
+/// cancel once dropped.
+pub(crate) struct StreamHandle {
+    id: String,
+    control_queue: Option<String>,
+    cancelled: Arc<Notify>,
+    cancels: Cancels,
+}
+
+impl StreamHandle {
+    /// Resolves once the caller cancelled the stream.
+    pub(crate) async fn cancelled(&self) {
+        self.cancelled.notified().await
+    }
+
+    pub(crate) fn chunk(&self, message: Message, seq: u64) -> Message {
+        self.tag(message, seq, false)
+    }
+
+    /// The last message of the stream: an empty end marker or an error reply.
+    pub(crate) fn end(&self, message: Message, seq: u64) -> Message {
+        self.tag(message, seq, true)
+    }
+
+    fn tag(&self, mut message: Message, seq: u64, end: bool) -> Message {
+        let headers = message.headers.get_or_insert_with(FieldTable::new);
+        let mut insert = |key: &str, value: FieldValue| {
+            headers.insert(FieldName::try_from(key).unwrap(), value);
+        };
+        insert(STREAM_ID_HEADER, FieldValue::S(LongStr::try_from(self.id.as_str()).unwrap()));
+        insert(STREAM_SEQ_HEADER, FieldValue::l(seq as i64));
+        if end {
+            insert(STREAM_END_HEADER, FieldValue::t(true));
+        }
+        if let Some(control_queue) = &self.control_queue {
+            insert(STREAM_CONTROL_HEADER, FieldValue::S(LongStr::try_from(control_queue.as_str()).unwrap()));
+        }
+        message
+    }
+}
+
+impl Drop for StreamHandle {
+    fn drop(&mut self) {
+        self.cancels.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// Stream headers of a received chunk.
+struct ChunkHeaders {
+    id: Option<String>,
+    seq: Option<u64>,
+    end: bool,
+    control_queue: Option<String>,
+}
+
+impl ChunkHeaders {
+    fn read(chunk: &Message) -> Self {
+        let headers = chunk.headers.as_ref().map(|h| h.as_ref());
+        let header = |key: &str| headers.and_then(|h| h.get(&FieldName::try_from(key).unwrap()));
+        let string = |key: &str| match header(key) {
+            Some(FieldValue::S(value)) => Some(value.to_string()),
+            _ => None,
+        };
+        Self {
+            id: string(STREAM_ID_HEADER),
+            seq: match header(STREAM_SEQ_HEADER) {
+                Some(FieldValue::l(seq)) => u64::try_from(*seq).ok(),
+                _ => None,
+            },
+            end: matches!(header(STREAM_END_HEADER), Some(FieldValue::t(true))),
+            control_queue: string(STREAM_CONTROL_HEADER),
+        }
+    }
+}
+
+struct Replies {
+    rx: UnboundedReceiver<Message>,
+    _pending: PendingReply,
+}
+
+struct ReplyStreamInner {
+    bus: Bus,
+    request: Mutex<Option<RpcRequest>>,
+    chunk_timeout: Duration,
+    replies: OnceCell<tokio::sync::Mutex<Option<Replies>>>,
+    next_seq: AtomicU64,
+    /// Stream id and control queue, known from the first chunk.
+    control: Mutex<Option<(String, String)>>,
+    finished: AtomicBool,
+}
+
+impl ReplyStreamInner {
+    /// Sends the request on first use, so the stream starts with the iteration.
+    async fn start(&self) -> PyResult<&tokio::sync::Mutex<Option<Replies>>> {
+        self.replies
+            .get_or_try_init(|| async {
+                let request = self.request.lock().unwrap().take().ok_or_else(|| {
+                    AppError::new(ErrorType::UnexpectedResult, "the stream request was already sent")
+                })?;
+                let (rx, pending) = self.bus.open_stream(request).await?;
+                Ok::<_, PyErr>(tokio::sync::Mutex::new(Some(Replies { rx, _pending: pending })))
+            })
+            .await
+    }
+
+    async fn next(&self) -> PyResult<Message> {
+        if self.finished.load(Ordering::SeqCst) {
+            return Err(PyStopAsyncIteration::new_err(()));
+        }
+        let replies = self.start().await?;
+        let mut replies = replies.lock().await;
+        let Some(rx) = replies.as_mut().map(|r| &mut r.rx) else {
+            return Err(PyStopAsyncIteration::new_err(()));
+        };
+        let chunk = match tokio::time::timeout(self.chunk_timeout, rx.recv()).await {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => {
+                replies.take();
+                self.finished.store(true, Ordering::SeqCst);
+                return Err(AppError::new(
+                    ErrorType::ConnectionClosed,
+                    "the reply channel closed before the stream ended",
+                )
+                .into());
+            }
+            Err(_) => {
+                replies.take();
+                drop(replies);
+                let _ = self.cancel().await;
+                return Err(AppError::new(
+                    ErrorType::ResponseTimeout,
+                    format!("no stream chunk within {:?}", self.chunk_timeout),
+                )
+                .into());
+            }
+        };
+        let headers = ChunkHeaders::read(&chunk);
+        if let (Some(id), Some(control_queue)) = (headers.id, headers.control_queue) {
+            self.control.lock().unwrap().get_or_insert((id, control_queue));
+        }
+        if let Some(error) = RemoteError::from_reply(&chunk) {
+            replies.take();
+            self.finished.store(true, Ordering::SeqCst);
+            return Err(error.into());
+        }
+        let expected = self.next_seq.fetch_add(1, Ordering::SeqCst);
+        if headers.seq != Some(expected) {
+            replies.take();
+            drop(replies);
+            let _ = self.cancel().await;
+            return Err(AppError::new(
+                ErrorType::UnexpectedResult,
+                format!("expected stream chunk {expected}, got {:?}", headers.seq),
+            )
+            .into());
+        }
+        if headers.end {
+            replies.take();
+            self.finished.store(true, Ordering::SeqCst);
+            return Err(PyStopAsyncIteration::new_err(()));
+        }
+        Ok(chunk)
+    }
+
+    /// Stops the provider's generator. A stream cancelled before its first
+    /// chunk arrived is cancelled once that chunk names its control queue.
+    async fn cancel(&self) -> Result<(), AppError> {
+        if self.finished.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        match self.control().await {
+            Some((stream_id, control_queue)) => self.bus.rpc.cancel_stream(&control_queue, &stream_id).await,
+            None => Ok(()),
+        }
+    }
+
+    /// The stream id and control queue, waiting up to `chunk_timeout` for the
+    /// first chunk when none arrived yet. `None` when the request was never
+    /// sent or the stream already ended.
+    async fn control(&self) -> Option<(String, String)> {
+        if self.request.lock().unwrap().take().is_some() {
+            return None;
+        }
+        // Waits for a `next` that is still receiving, which may learn the
+        // control queue itself.
+        let mut replies = self.start().await.ok()?.lock().await;
+        if let Some(control) = self.control.lock().unwrap().take() {
+            return Some(control);
+        }
+        let rx = &mut replies.as_mut()?.rx;
+        let chunk = tokio::time::timeout(self.chunk_timeout, rx.recv()).await.ok()??;
+        replies.take();
+        let headers = ChunkHeaders::read(&chunk);
+        if headers.end {
+            return None;
+        }
+        headers.id.zip(headers.control_queue)
+    }
+}
+
+/// Async iterator over the chunks of a streamed RPC reply. Leaving the
+/// iteration early, a chunk timeout and garbage collection cancel the stream
+/// on the provider.
+#[pyclass(skip_from_py_object)]
+pub struct ReplyStream {
+    inner: Arc<ReplyStreamInner>,
+}
+
+impl ReplyStream {
+    pub(crate) fn new(bus: Bus, request: RpcRequest, chunk_timeout: Duration) -> Self {
+        Self {
+            inner: Arc::new(ReplyStreamInner {
+                bus,
+                request: Mutex::new(Some(request)),
+                chunk_timeout,
+                replies: OnceCell::new(),
+                next_seq: AtomicU64::new(0),
+                control: Mutex::new(None),
+                finished: AtomicBool::new(false),
+            }),
+        }
+    }
+}
+
+impl Drop for ReplyStream {
+    fn drop(&mut self) {
+        if self.inner.finished.load(Ordering::SeqCst) {
+            return;
+        }
+        let inner = Arc::clone(&self.inner);
+        pyo3_async_runtimes::tokio::get_runtime().spawn(async move {
+            let _ = inner.cancel().await;
+        });
+    }
+}
+
+#[pymethods]
+impl ReplyStream {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__(slf: PyRef<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
+        let inner = Arc::clone(&slf.inner);
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move { inner.next().await })
+    }
+
+    /// Cancels the stream on the provider. Chunks still in flight are dropped.
+    fn aclose(slf: PyRef<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
+        let inner = Arc::clone(&slf.inner);
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            inner.cancel().await.map_err(Into::into)
+        })
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use pyo3::{exceptions::PyStopAsyncIteration, prelude::*};
+use tokio::sync::{mpsc, Mutex, Notify, OnceCell};
+
+use crate::{
+    consumer::{Handler, HandlerFuture, SubscribeSpec, Subscriber, SubscriptionState},
+    exceptions::AppError,
+    message::Message,
+};
+
+/// Buffer of a `MessageStream` consuming without a prefetch limit.
+const DEFAULT_STREAM_CAPACITY: usize = 64;
+
+/// Handle of one `subscribe` or `provide_resource` call. Dropping it leaves
+/// the consumer running; only `cancel` or `dispose` stop it.
+#[pyclass(skip_from_py_object)]
+pub struct Subscription {
+    subscriber: Arc<Subscriber>,
+    state: Arc<SubscriptionState>,
+    /// Set for subscriptions of a `SyncEventbus`, whose methods block instead
+    /// of returning awaitables.
+    blocking: bool,
+}
+
+impl Subscription {
+    pub(crate) fn new(subscriber: Arc<Subscriber>, state: Arc<SubscriptionState>, blocking: bool) -> Self {
+        Self {
+            subscriber,
+            state,
+            blocking,
+        }
+    }
+
+    fn run<'py>(
+        &self,
+        py: Python<'py>,
+        command: impl Future<Output = Result<(), AppError>> + Send + 'static,
+    ) -> PyResult<Bound<'py, PyAny>> {
+        if self.blocking {
+            py.detach(|| pyo3_async_runtimes::tokio::get_runtime().block_on(command))?;
+            return Ok(py.None().into_bound(py));
+        }
+        pyo3_async_runtimes::tokio::future_into_py(py, async move { command.await.map_err(Into::into) })
+    }
+}
+
+#[pymethods]
+impl Subscription {
+    /// Tag of the live consumer. It changes when the consumer is re-created
+    /// after a reconnect or `resume`, and is `None` while paused or cancelled.
+    #[getter]
+    fn consumer_tag(&self) -> Option<String> {
+        self.state.consumer_tag()
+    }
+    #[getter]
+    fn queue_name(&self) -> &str {
+        self.subscriber.queue_name()
+    }
+    #[getter]
+    fn delivered(&self) -> u64 {
+        self.state.delivered.load(Ordering::Relaxed)
+    }
+    #[getter]
+    fn handled(&self) -> u64 {
+        self.state.handled.load(Ordering::Relaxed)
+    }
+    #[getter]
+    fn failed(&self) -> u64 {
+        self.state.failed.load(Ordering::Relaxed)
+    }
+    #[getter]
+    fn paused(&self) -> bool {
+        self.state.is_paused()
+    }
+    #[getter]
+    fn cancelled(&self) -> bool {
+        self.state.is_cancelled()
+    }
+
+    fn cancel(slf: PyRef<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
+        let subscriber = Arc::clone(&slf.subscriber);
+        let state = Arc::clone(&slf.state);
+        slf.run(slf.py(), async move { subscriber.cancel(&state).await })
+    }
+
+    fn pause(slf: PyRef<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
+        let subscriber = Arc::clone(&slf.subscriber);
+        let state = Arc::clone(&slf.state);
+        slf.run(slf.py(), async move { subscriber.pause(&state).await })
+    }
+
+    #[pyo3(signature = (command_timeout=Some(16)))]
+    fn resume(slf: PyRef<'_, Self>, command_timeout: Option<u64>) -> PyResult<Bound<'_, PyAny>> {
+        let subscriber = Arc::clone(&slf.subscriber);
+        let state = Arc::clone(&slf.state);
+        let command_timeout = command_timeout.map(Duration::from_secs);
+        slf.run(slf.py(), async move { subscriber.resume(&state, command_timeout).await })
+    }
+
+    fn __repr__(&self) -> String {
+        format!(
+            "Subscription(queue_name={:?}, consumer_tag={:?}, delivered={}, handled={}, failed={})",
+            self.subscriber.queue_name(),
+            self.state.consumer_tag(),
+            self.delivered(),
+            self.handled(),
+            self.failed()
+        )
+    }
+}
+
+struct StreamInner {
+    subscriber: Arc<Subscriber>,
+    spec: SubscribeSpec,
+    handler: Handler,
+    command_timeout: Option<Duration>,
+    state: OnceCell<Arc<SubscriptionState>>,
+    rx: Mutex<mpsc::Receiver<Message>>,
+    closed: AtomicBool,
+    closing: Notify,
+}
+
+impl StreamInner {
+    /// Subscribes on first use, so the consumer starts with the iteration.
+    async fn start(&self) -> Result<&Arc<SubscriptionState>, AppError> {
+        self.state
+            .get_or_try_init(|| {
+                self.subscriber.subscribe(
+                    self.spec.clone(),
+                    Arc::clone(&self.handler),
+                    None,
+                    self.command_timeout,
+                )
+            })
+            .await
+    }
+
+    async fn next(&self) -> PyResult<Message> {
+        let closing = self.closing.notified();
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(PyStopAsyncIteration::new_err(()));
+        }
+        let state = self.start().await?;
+        if self.closed.load(Ordering::SeqCst) {
+            self.subscriber.cancel(state).await?;
+            return Err(PyStopAsyncIteration::new_err(()));
+        }
+        let mut rx = self.rx.lock().await;
+        tokio::select! {
+            message = rx.recv() => message.ok_or_else(|| PyStopAsyncIteration::new_err(())),
+            _ = closing => Err(PyStopAsyncIteration::new_err(())),
+        }
+    }
+
+    async fn close(&self) -> Result<(), AppError> {
+        if self.closed.swap(true, Ordering::SeqCst) {
+            return Ok(());
+        }
+        self.closing.notify_waiters();
+        match self.state.get() {
+            Some(state) => self.subscriber.cancel(state).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Async iterator over the deliveries of one binding, fed by the same
+/// consumer as `subscribe`. Messages are never settled on the reader's
+/// behalf: each one must be acked, nacked or rejected.
+#[pyclass(skip_from_py_object)]
+pub struct MessageStream {
+    inner: Arc<StreamInner>,
+}
+
+impl MessageStream {
+    pub(crate) fn new(subscriber: Arc<Subscriber>, spec: SubscribeSpec, command_timeout: Option<Duration>) -> Self {
+        let capacity = spec.prefetch.map_or(DEFAULT_STREAM_CAPACITY, usize::from).max(1);
+        let (tx, rx) = mpsc::channel(capacity);
+        // The sender waits for room, holding the delivery unsettled, so a
+        // slow reader pushes back on the broker through the prefetch window.
+        let handler: Handler = Arc::new(move |message: Message| -> HandlerFuture {
+            let tx = tx.clone();
+            Box::pin(async move {
+                match tx.send(message).await {
+                    Ok(()) => Ok(None),
+                    Err(_) => Err("the message stream was closed".into()),
+                }
+            })
+        });
+        Self {
+            inner: Arc::new(StreamInner {
+                subscriber,
+                spec: SubscribeSpec {
+                    manual_settle: true,
+                    ..spec
+                },
+                handler,
+                command_timeout,
+                state: OnceCell::new(),
+                rx: Mutex::new(rx),
+                closed: AtomicBool::new(false),
+                closing: Notify::new(),
+            }),
+        }
+    }
+}
+
+impl Drop for MessageStream {
+    fn drop(&mut self) {
+        if self.inner.closed.load(Ordering::SeqCst) {
+            return;
+        }
+        let inner = Arc::clone(&self.inner);
+        pyo3_async_runtimes::tokio::get_runtime().spawn(async move {
+            let _ = inner.close().await;
+        });
+    }
+}
+
+#[pymethods]
+impl MessageStream {
+    fn __aiter__(slf: PyRef<'_, Self>) -> PyRef<'_, Self> {
+        slf
+    }
+
+    fn __anext__(slf: PyRef<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
+        let inner = Arc::clone(&slf.inner);
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move { inner.next().await })
+    }
+
+    /// Cancels the consumer. Unsettled messages go back to the queue.
+    fn aclose(slf: PyRef<'_, Self>) -> PyResult<Bound<'_, PyAny>> {
+        let inner = Arc::clone(&slf.inner);
+        pyo3_async_runtimes::tokio::future_into_py(slf.py(), async move {
+            inner.close().await.map_err(Into::into)
+        })
+    }
+
+    #[getter]
+    fn queue_name(&self) -> &str {
+        self.inner.subscriber.queue_name()
+    }
+    #[getter]
+    fn consumer_tag(&self) -> Option<String> {
+        self.inner.state.get().and_then(|state| state.consumer_tag())
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    future::Future,
+    sync::{mpsc, Arc, Mutex},
+    thread,
+};
+
+use pyo3::{prelude::*, types::PyDict};
+use tokio::sync::oneshot;
+
+use crate::{
+    bus::{subscribe_spec, Bus, PublishRequest, RpcRequest},
+    consumer::{Handler, HandlerError, HandlerFuture, Reply, SubscribeSpec},
+    message::Message,
+    no_reply, rpc_reply,
+    rpc::RemoteError,
+    subscription::Subscription,
+    topology::{ExchangeKind, QueueOptions},
+    Config, ContentEncoding, DeliveryMode, Payload, PublishBody, QoSConfig,
+};
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Threads that run the handlers of a `SyncEventbus`, so a slow handler
+/// never stalls the tokio workers that consume and acknowledge.
+struct HandlerPool {
+    jobs: mpsc::Sender<Job>,
+}
+
+impl HandlerPool {
+    fn new(threads: usize) -> std::io::Result<Self> {
+        let (jobs, rx) = mpsc::channel::<Job>();
+        let rx = Arc::new(Mutex::new(rx));
+        for i in 0..threads.max(1) {
+            let rx = Arc::clone(&rx);
+            thread::Builder::new()
+                .name(format!("amqp-rs-handler-{i}"))
+                .spawn(move || {
+                    // The lock is only held while waiting for the next job.
+                    while let Ok(job) = { rx.lock().unwrap().recv() } {
+                        job();
+                    }
+                })?;
+        }
+        Ok(Self { jobs })
+    }
+
+    async fn run<T: Send + 'static>(
+        &self,
+        job: impl FnOnce() -> T + Send + 'static,
+    ) -> Result<T, HandlerError> {
+        let (tx, rx) = oneshot::channel();
+        self.jobs
+            .send(Box::new(move || {
+                let _ = tx.send(job());
+            }))
+            .map_err(|_| "the handler pool has stopped")?;
+        rx.await.map_err(|_| "the handler panicked".into())
+    }
+}
+
+/// Adapts a plain Python callable to a consumer `Handler` that runs it on the
+/// handler pool.
+fn pooled_handler(
+    pool: Arc<HandlerPool>,
+    handler: Py<PyAny>,
+    reply: fn(Python<'_>, Py<PyAny>) -> Result<Option<Message>, HandlerError>,
+) -> Handler {
+    let handler = Arc::new(handler);
+    Arc::new(move |message: Message| -> HandlerFuture {
+        let pool = Arc::clone(&pool);
+        let handler = Arc::clone(&handler);
+        Box::pin(async move {
+            pool.run(move || {
+                Python::attach(|py| match handler.call1(py, (message,)) {
+                    Ok(result) => reply(py, result).map(|message| message.map(|message| Reply::Message(Box::new(message)))),
+                    Err(e) => Err(RemoteError::from_py(py, &e).into()),
+                })
+            })
+            .await?
+        })
+    })
+}
+
+/// Blocking counterpart of `AsyncEventbus` for code without an asyncio loop.
+/// Calls wait on the shared tokio runtime with the GIL released.
+#[pyclass(skip_from_py_object)]
+pub struct SyncEventbus {
+    bus: Bus,
+    pool: Arc<HandlerPool>,
+}
+
+impl SyncEventbus {
+    fn block_on<T: Send>(py: Python<'_>, future: impl Future<Output = PyResult<T>> + Send) -> PyResult<T> {
+        py.detach(|| pyo3_async_runtimes::tokio::get_runtime().block_on(future))
+    }
+}
+
+#[pymethods]
+impl SyncEventbus {
+    #[new]
+    #[pyo3(signature = (config, qos_config, handler_threads=4))]
+    fn new(config: Config, qos_config: QoSConfig, handler_threads: usize) -> PyResult<Self> {
+        Ok(Self {
+            bus: Bus::new(config, qos_config)?,
+            pool: Arc::new(HandlerPool::new(handler_threads)?),
+        })
+    }
+
+    #[pyo3(signature = (exchange_name, routing_key, body, content_type=Some("application/json"), content_encoding=ContentEncoding::Null, command_timeout=16, delivery_mode=DeliveryMode::Transient, expiration=None, headers=None, message_id=None, correlation_id=None, priority=None, timestamp=None, app_id=None, r#type=None, user_id=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn publish<'py>(
+        &self,
+        py: Python<'py>,
+        exchange_name: &'py str,
+        routing_key: &'py str,
+        body: PublishBody<'py>,
+        content_type: Option<&'py str>,
+        content_encoding: ContentEncoding,
+        command_timeout: Option<u64>,
+        delivery_mode: DeliveryMode,
+        expiration: Option<u32>,
+        headers: Option<Bound<'py, PyDict>>,
+        message_id: Option<String>,
+        correlation_id: Option<String>,
+        priority: Option<u8>,
+        timestamp: Option<u64>,
+        app_id: Option<String>,
+        r#type: Option<String>,
+        user_id: Option<String>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<()> {
+        let request = PublishRequest::new(
+            exchange_name,
+            routing_key,
+            body,
+            content_type,
+            content_encoding,
+            command_timeout,
+            delivery_mode,
+            expiration,
+            headers,
+            message_id,
+            correlation_id,
+            priority,
+            timestamp,
+            app_id,
+            r#type,
+            user_id,
+            exchange_type,
+            exchange_arguments,
+        )?;
+        Self::block_on(py, self.bus.publish(request))
+    }
+
+    #[pyo3(signature = (exchange_name, routing_key, body, content_type="application/json", content_encoding=ContentEncoding::Null, response_timeout=20_000, connection_timeout=Some(32), delivery_mode=DeliveryMode::Transient, expiration=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn rpc_client<'py>(
+        &self,
+        py: Python<'py>,
+        exchange_name: &str,
+        routing_key: &str,
+        body: Payload<'py>,
+        content_type: &str,
+        content_encoding: ContentEncoding,
+        response_timeout: u32,
+        connection_timeout: Option<u64>,
+        delivery_mode: DeliveryMode,
+        expiration: Option<u32>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<Py<PyAny>> {
+        let request = RpcRequest::new(
+            exchange_name,
+            routing_key,
+            body,
+            content_type,
+            content_encoding,
+            response_timeout,
+            connection_timeout,
+            delivery_mode,
+            expiration,
+            exchange_type,
+            exchange_arguments,
+        )?;
+        Self::block_on(py, self.bus.rpc_client(request))
+    }
+
+    #[pyo3(signature = (exchange_name, routing_key, body, content_type="application/json", content_encoding=ContentEncoding::Null, response_timeout=20_000, connection_timeout=Some(32), delivery_mode=DeliveryMode::Transient, expiration=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn rpc_call<'py>(
+        &self,
+        py: Python<'py>,
+        exchange_name: &str,
+        routing_key: &str,
+        body: Payload<'py>,
+        content_type: &str,
+        content_encoding: ContentEncoding,
+        response_timeout: u32,
+        connection_timeout: Option<u64>,
+        delivery_mode: DeliveryMode,
+        expiration: Option<u32>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<Message> {
+        let request = RpcRequest::new(
+            exchange_name,
+            routing_key,
+            body,
+            content_type,
+            content_encoding,
+            response_timeout,
+            connection_timeout,
+            delivery_mode,
+            expiration,
+            exchange_type,
+            exchange_arguments,
+        )?;
+        Self::block_on(py, self.bus.rpc_call(request))
+    }
+
+    #[pyo3(signature = (exchange_name, routing_key, body, timeout, max_replies=None, content_type="application/json", content_encoding=ContentEncoding::Null, connection_timeout=Some(32), delivery_mode=DeliveryMode::Transient, expiration=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn rpc_gather<'py>(
+        &self,
+        py: Python<'py>,
+        exchange_name: &str,
+        routing_key: &str,
+        body: Payload<'py>,
+        timeout: u32,
+        max_replies: Option<usize>,
+        content_type: &str,
+        content_encoding: ContentEncoding,
+        connection_timeout: Option<u64>,
+        delivery_mode: DeliveryMode,
+        expiration: Option<u32>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<Vec<Message>> {
+        let request = RpcRequest::new(
+            exchange_name,
+            routing_key,
+            body,
+            content_type,
+            content_encoding,
+            timeout,
+            connection_timeout,
+            delivery_mode,
+            expiration,
+            exchange_type,
+            exchange_arguments,
+        )?;
+        Self::block_on(py, self.bus.rpc_gather(request, max_replies))
+    }
+
+    #[pyo3(signature = (exchange_name, routing_key, handler, process_timeout=None, command_timeout=Some(16), queue_options=None, exchange_type=None, exchange_arguments=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn subscribe<'py>(
+        &self,
+        py: Python<'py>,
+        exchange_name: &str,
+        routing_key: &str,
+        handler: Py<PyAny>,
+        process_timeout: Option<u64>,
+        command_timeout: Option<u64>,
+        queue_options: Option<QueueOptions>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+    ) -> PyResult<Subscription> {
+        let handler = pooled_handler(Arc::clone(&self.pool), handler, no_reply);
+        let spec = subscribe_spec(exchange_name, routing_key, queue_options, exchange_type, exchange_arguments)?;
+        let state = Self::block_on(py, async {
+            self.bus
+                .subscribe(spec, handler, process_timeout, command_timeout)
+                .await
+                .map_err(Into::into)
+        })?;
+        Ok(Subscription::new(Arc::clone(&self.bus.subscriber), state, true))
+    }
+
+    #[pyo3(signature = (routing_key, handler, process_timeout=None, command_timeout=None, queue_options=None, exchange_type=None, exchange_arguments=None, include_traceback=false))]
+    #[allow(clippy::too_many_arguments)]
+    fn provide_resource<'py>(
+        &self,
+        py: Python<'py>,
+        routing_key: &str,
+        handler: Py<PyAny>,
+        process_timeout: Option<u64>,
+        command_timeout: Option<u64>,
+        queue_options: Option<QueueOptions>,
+        exchange_type: Option<ExchangeKind>,
+        exchange_arguments: Option<Bound<'py, PyDict>>,
+        include_traceback: bool,
+    ) -> PyResult<Subscription> {
+        let handler = pooled_handler(Arc::clone(&self.pool), handler, rpc_reply);
+        let spec = SubscribeSpec {
+            include_traceback,
+            ..subscribe_spec(
+                &self.bus.rpc_exchange_name,
+                routing_key,
+                queue_options,
+                exchange_type,
+                exchange_arguments,
+            )?
+        };
+        let state = Self::block_on(py, async {
+            self.bus
+                .provide_resource(spec, handler, process_timeout, command_timeout)
+                .await
+                .map_err(Into::into)
+        })?;
+        Ok(Subscription::new(Arc::clone(&self.bus.rpc_server), state, true))
+    }
+
+    fn dispose(&self, py: Python<'_>) -> PyResult<()> {
+        Self::block_on(py, self.bus.dispose())
+    }
+}
@@ -0,0 +1,461 @@
+use std::{sync::Arc, time::Duration};
+
+use amqp_client_rust::amqprs::{
+    channel::{
+        Channel, ExchangeBindArguments, ExchangeDeclareArguments, ExchangeDeleteArguments,
+        ExchangeUnbindArguments, QueueBindArguments, QueueDeclareArguments, QueueDeleteArguments,
+        QueuePurgeArguments, QueueUnbindArguments,
+    },
+    FieldName, FieldTable, FieldValue, LongStr,
+};
+use pyo3::prelude::*;
+
+use crate::{
+    connection::Connector,
+    exceptions::{channel_error, AppError, ErrorType},
+};
+
+/// Result of a queue declaration. A passive declaration reports the state of
+/// an existing queue without creating it.
+#[pyclass(skip_from_py_object, get_all)]
+#[derive(Debug, Clone)]
+pub struct QueueInfo {
+    pub name: String,
+    pub message_count: u32,
+    pub consumer_count: u32,
+}
+
+#[pymethods]
+impl QueueInfo {
+    fn __repr__(&self) -> String {
+        format!(
+            "QueueInfo(name={:?}, message_count={}, consumer_count={})",
+            self.name, self.message_count, self.consumer_count
+        )
+    }
+}
+
+#[pyclass(from_py_object, get_all, set_all)]
+#[derive(Debug, Clone)]
+pub enum ExchangeType {
+    Direct,
+    Topic,
+    Fanout,
+    Headers,
+}
+
+/// An `ExchangeType`, or the type name of a plugin exchange such as
+/// `x-delayed-message` or `x-consistent-hash`.
+#[derive(FromPyObject)]
+pub(crate) enum ExchangeKind {
+    Builtin(ExchangeType),
+    Custom(String),
+}
+
+impl ExchangeKind {
+    pub(crate) fn into_string(self) -> String {
+        match self {
+            ExchangeKind::Builtin(ExchangeType::Direct) => "direct".to_owned(),
+            ExchangeKind::Builtin(ExchangeType::Topic) => "topic".to_owned(),
+            ExchangeKind::Builtin(ExchangeType::Fanout) => "fanout".to_owned(),
+            ExchangeKind::Builtin(ExchangeType::Headers) => "headers".to_owned(),
+            ExchangeKind::Custom(name) => name,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ExchangeSpec {
+    pub name: String,
+    pub exchange_type: String,
+    pub durable: bool,
+    pub auto_delete: bool,
+    pub internal: bool,
+    pub passive: bool,
+    pub arguments: FieldTable,
+}
+
+impl ExchangeSpec {
+    /// The durable exchange that `publish`, `subscribe` and the RPC paths
+    /// declare on the caller's behalf.
+    pub(crate) fn durable(name: &str, exchange_type: String, arguments: FieldTable) -> Self {
+        Self {
+            name: name.to_owned(),
+            exchange_type,
+            durable: true,
+            auto_delete: false,
+            internal: false,
+            passive: false,
+            arguments,
+        }
+    }
+
+    /// Whether the exchange routes regardless of the routing key, so the
+    /// binding key says nothing about which deliveries it produces.
+    pub(crate) fn ignores_routing_key(&self) -> bool {
+        matches!(self.exchange_type.as_str(), "fanout" | "headers")
+    }
+}
+
+/// Declares `spec` on `channel`. The broker only reports a conflict as a bare
+/// PRECONDITION_FAILED, so the error is reworded to name what was declared.
+pub(crate) async fn declare_exchange(channel: &Channel, spec: &ExchangeSpec) -> Result<(), AppError> {
+    channel
+        .exchange_declare(
+            ExchangeDeclareArguments::new(&spec.name, &spec.exchange_type)
+                .durable(spec.durable)
+                .auto_delete(spec.auto_delete)
+                .internal(spec.internal)
+                .passive(spec.passive)
+                .arguments(spec.arguments.clone())
+                .finish(),
+        )
+        .await
+        .map_err(|e| {
+            let error = channel_error(channel)(e);
+            if error.error_type != ErrorType::PreconditionFailed {
+                return error;
+            }
+            AppError {
+                message: Some(format!(
+                    "exchange '{}' already exists with a type, durability or arguments other than the declared {} exchange (durable={})",
+                    spec.name, spec.exchange_type, spec.durable
+                )),
+                description: error.message,
+                error_type: ErrorType::PreconditionFailed,
+            }
+        })
+}
+
+pub(crate) struct QueueSpec {
+    pub name: String,
+    pub durable: bool,
+    pub exclusive: bool,
+    pub auto_delete: bool,
+    pub passive: bool,
+    pub arguments: FieldTable,
+}
+
+/// Explicit exchange and queue management. Every command runs on its own
+/// short-lived channel because the broker closes the channel on any refusal.
+pub(crate) struct Topology {
+    connector: Arc<Connector>,
+}
+
+impl Topology {
+    pub(crate) fn new(connector: Arc<Connector>) -> Self {
+        Self { connector }
+    }
+
+    async fn run<T>(
+        &self,
+        timeout: Option<Duration>,
+        command: impl AsyncFnOnce(&Channel) -> Result<T, AppError>,
+    ) -> Result<T, AppError> {
+        let channel = self.connector.open_channel(timeout).await?;
+        let result = command(&channel).await;
+        if channel.is_open() {
+            let _ = channel.close().await;
+        }
+        result
+    }
+
+    pub(crate) async fn declare_exchange(&self, spec: ExchangeSpec, timeout: Option<Duration>) -> Result<(), AppError> {
+        self.run(timeout, async |channel| declare_exchange(channel, &spec).await)
+            .await
+    }
+
+    pub(crate) async fn declare_queue(&self, spec: QueueSpec, timeout: Option<Duration>) -> Result<QueueInfo, AppError> {
+        self.run(timeout, async |channel| {
+            let declared = channel
+                .queue_declare(
+                    QueueDeclareArguments::new(&spec.name)
+                        .durable(spec.durable)
+                        .exclusive(spec.exclusive)
+                        .auto_delete(spec.auto_delete)
+                        .passive(spec.passive)
+                        .arguments(spec.arguments)
+                        .finish(),
+                )
+                .await
+                .map_err(channel_error(channel))?;
+            let (name, message_count, consumer_count) = declared.ok_or_else(|| {
+                AppError::new(ErrorType::UnexpectedResult, "queue.declare-ok was not received")
+            })?;
+            Ok(QueueInfo {
+                name,
+                message_count,
+                consumer_count,
+            })
+        })
+        .await
+    }
+
+    pub(crate) async fn bind_queue(
+        &self,
+        queue: &str,
+        exchange: &str,
+        routing_key: &str,
+        arguments: FieldTable,
+        timeout: Option<Duration>,
+    ) -> Result<(), AppError> {
+        self.run(timeout, async |channel| {
+            channel
+                .queue_bind(
+                    QueueBindArguments::new(queue, exchange, routing_key)
+                        .arguments(arguments)
+                        .finish(),
+                )
+                .await
+                .map_err(channel_error(channel))
+        })
+        .await
+    }
+
+    pub(crate) async fn unbind_queue(
+        &self,
+        queue: &str,
+        exchange: &str,
+        routing_key: &str,
+        arguments: FieldTable,
+        timeout: Option<Duration>,
+    ) -> Result<(), AppError> {
+        self.run(timeout, async |channel| {
+            channel
+                .queue_unbind(
+                    QueueUnbindArguments::new(queue, exchange, routing_key)
+                        .arguments(arguments)
+                        .finish(),
+                )
+                .await
+                .map_err(channel_error(channel))
+        })
+        .await
+    }
+
+    pub(crate) async fn bind_exchange(
+        &self,
+        destination: &str,
+        source: &str,
+        routing_key: &str,
+        arguments: FieldTable,
+        timeout: Option<Duration>,
+    ) -> Result<(), AppError> {
+        self.run(timeout, async |channel| {
+            channel
+                .exchange_bind(
+                    ExchangeBindArguments::new(destination, source, routing_key)
+                        .arguments(arguments)
+                        .finish(),
+                )
+                .await
+                .map_err(channel_error(channel))
+        })
+        .await
+    }
+
+    pub(crate) async fn unbind_exchange(
+        &self,
+        destination: &str,
+        source: &str,
+        routing_key: &str,
+        arguments: FieldTable,
+        timeout: Option<Duration>,
+    ) -> Result<(), AppError> {
+        self.run(timeout, async |channel| {
+            channel
+                .exchange_unbind(
+                    ExchangeUnbindArguments::new(destination, source, routing_key)
+                        .arguments(arguments)
+                        .finish(),
+                )
+                .await
+                .map_err(channel_error(channel))
+        })
+        .await
+    }
+
+    /// Returns the number of messages deleted with the queue.
+    pub(crate) async fn delete_queue(
+        &self,
+        queue: &str,
+        if_unused: bool,
+        if_empty: bool,
+        timeout: Option<Duration>,
+    ) -> Result<u32, AppError> {
+        self.run(timeout, async |channel| {
+            channel
+                .queue_delete(
+                    QueueDeleteArguments::new(queue)
+                        .if_unused(if_unused)
+                        .if_empty(if_empty)
+                        .finish(),
+                )
+                .await
+                .map(Option::unwrap_or_default)
+                .map_err(channel_error(channel))
+        })
+        .await
+    }
+
+    pub(crate) async fn delete_exchange(
+        &self,
+        exchange: &str,
+        if_unused: bool,
+        timeout: Option<Duration>,
+    ) -> Result<(), AppError> {
+        self.run(timeout, async |channel| {
+            channel
+                .exchange_delete(ExchangeDeleteArguments::new(exchange).if_unused(if_unused).finish())
+                .await
+                .map_err(channel_error(channel))
+        })
+        .await
+    }
+
+    /// Returns the number of messages purged.
+    pub(crate) async fn purge_queue(&self, queue: &str, timeout: Option<Duration>) -> Result<u32, AppError> {
+        self.run(timeout, async |channel| {
+            channel
+                .queue_purge(QueuePurgeArguments::new(queue))
+                .await
+                .map(Option::unwrap_or_default)
+                .map_err(channel_error(channel))
+        })
+        .await
+    }
+}
+
+#[pyclass(from_py_object, get_all, set_all)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum QueueType {
+    Classic,
+    Quorum,
+    Stream,
+}
+
+#[pyclass(from_py_object, get_all, set_all)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Overflow {
+    DropHead,
+    RejectPublish,
+    RejectPublishDlx,
+}
+
+/// Declaration settings for the queue a subscription or RPC provider consumes
+/// from. The first declaration wins: declaring the same queue again with
+/// different settings fails with `PreconditionFailedError`, and so does
+/// subscribing with settings other than the queue's live subscriptions.
+#[pyclass(from_py_object, get_all, set_all)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct QueueOptions {
+    pub durable: bool,
+    pub exclusive: bool,
+    pub auto_delete: bool,
+    pub message_ttl: Option<u32>,
+    pub max_length: Option<u32>,
+    pub overflow: Option<Overflow>,
+    pub dead_letter_exchange: Option<String>,
+    pub dead_letter_routing_key: Option<String>,
+    pub queue_type: Option<QueueType>,
+    pub single_active_consumer: bool,
+    pub max_priority: Option<u8>,
+}
+
+#[pymethods]
+impl QueueOptions {
+    #[new]
+    #[pyo3(signature = (durable=true, exclusive=false, auto_delete=false, message_ttl=None, max_length=None, overflow=None, dead_letter_exchange=None, dead_letter_routing_key=None, queue_type=None, single_active_consumer=false, max_priority=None))]
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        durable: bool,
+        exclusive: bool,
+        auto_delete: bool,
+        message_ttl: Option<u32>,
+        max_length: Option<u32>,
+        overflow: Option<Overflow>,
+        dead_letter_exchange: Option<String>,
+        dead_letter_routing_key: Option<String>,
+        queue_type: Option<QueueType>,
+        single_active_consumer: bool,
+        max_priority: Option<u8>,
+    ) -> Self {
+        Self {
+            durable,
+            exclusive,
+            auto_delete,
+            message_ttl,
+            max_length,
+            overflow,
+            dead_letter_exchange,
+            dead_letter_routing_key,
+            queue_type,
+            single_active_consumer,
+            max_priority,
+        }
+    }
+
+    #[staticmethod]
+    #[pyo3(name = "default")]
+    fn py_default() -> Self {
+        Self::default()
+    }
+}
+
+impl Default for QueueOptions {
+    fn default() -> Self {
+        Self::new(true, false, false, None, None, None, None, None, None, false, None)
+    }
+}
+
+impl QueueOptions {
+    pub(crate) fn to_spec(&self, name: &str) -> QueueSpec {
+        let mut arguments = FieldTable::new();
+        let mut insert = |key: &str, value: FieldValue| {
+            arguments.insert(FieldName::try_from(key).unwrap(), value);
+        };
+        let string = |value: &str| FieldValue::S(LongStr::try_from(value).unwrap());
+        if let Some(ttl) = self.message_ttl {
+            insert("x-message-ttl", FieldValue::l(ttl.into()));
+        }
+        if let Some(max_length) = self.max_length {
+            insert("x-max-length", FieldValue::l(max_length.into()));
+        }
+        if let Some(overflow) = &self.overflow {
+            let overflow = match overflow {
+                Overflow::DropHead => "drop-head",
+                Overflow::RejectPublish => "reject-publish",
+                Overflow::RejectPublishDlx => "reject-publish-dlx",
+            };
+            insert("x-overflow", string(overflow));
+        }
+        if let Some(exchange) = &self.dead_letter_exchange {
+            insert("x-dead-letter-exchange", string(exchange));
+        }
+        if let Some(routing_key) = &self.dead_letter_routing_key {
+            insert("x-dead-letter-routing-key", string(routing_key));
+        }
+        if let Some(queue_type) = &self.queue_type {
+            let queue_type = match queue_type {
+                QueueType::Classic => "classic",
+                QueueType::Quorum => "quorum",
+                QueueType::Stream => "stream",
+            };
+            insert("x-queue-type", string(queue_type));
+        }
+        if self.single_active_consumer {
+            insert("x-single-active-consumer", FieldValue::t(true));
+        }
+        if let Some(max_priority) = self.max_priority {
+            insert("x-max-priority", FieldValue::l(max_priority.into()));
+        }
+        QueueSpec {
+            name: name.to_owned(),
+            durable: self.durable,
+            exclusive: self.exclusive,
+            auto_delete: self.auto_delete,
+            passive: false,
+            arguments,
+        }
+    }
+}
@@ -0,0 +1,24 @@
+import pytest
+from amqp_rs import (
+    AsyncEventbus, Config, ConfigOptions, QoSConfig,
+    AmqpError, NackError, PublishTimeoutError, ResponseTimeoutError,
+    AutoReconnectError, ConnectionClosedError, UnexpectedResultError,
+    InternalError, OperationTimeoutError, UnsupportedContentTypeError,
+)
+
+
+def test_exception_hierarchy():
+    for error in (NackError, PublishTimeoutError, ResponseTimeoutError, AutoReconnectError, ConnectionClosedError, UnexpectedResultError,
+                  InternalError, OperationTimeoutError, UnsupportedContentTypeError):
+        assert issubclass(error, AmqpError)
+    assert issubclass(AmqpError, Exception)
+
+
+@pytest.mark.asyncio
+async def test_publish_unreachable_broker():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5999, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    with pytest.raises(AutoReconnectError) as error:
+        await eventbus.publish("example", "abc.example", b"hi", command_timeout=1)
+    assert error.value.error_type == "AutoReconnect"
+    await eventbus.dispose()
@@ -0,0 +1,61 @@
+from amqp_rs import Message, DeliveryMode
+from datetime import datetime, timezone
+from decimal import Decimal
+import pytest
+
+
+def test_message_properties():
+    message = Message.new(
+        b"body",
+        content_type="application/json",
+        headers={"x-trace": "abc"},
+        delivery_mode=DeliveryMode.Persistent,
+        priority=5,
+        correlation_id="corr-1",
+        reply_to="replies",
+        expiration="60000",
+        message_id="msg-1",
+        timestamp=1_700_000_000,
+        type="user.created",
+        user_id="guest",
+        app_id="tests",
+    )
+    assert message.body == b"body"
+    assert message.content_type == "application/json"
+    assert message.delivery_mode == DeliveryMode.Persistent
+    assert message.priority == 5
+    assert message.correlation_id == "corr-1"
+    assert message.reply_to == "replies"
+    assert message.expiration == "60000"
+    assert message.message_id == "msg-1"
+    assert message.timestamp == 1_700_000_000
+    assert message.type == "user.created"
+    assert message.user_id == "guest"
+    assert message.app_id == "tests"
+
+
+def test_message_headers_round_trip():
+    stamp = datetime(2024, 1, 1, tzinfo=timezone.utc)
+    headers = {
+        "str": "value",
+        "int": -42,
+        "float": 1.5,
+        "bool": True,
+        "none": None,
+        "bytes": b"\x00\x01",
+        "decimal": Decimal("12.34"),
+        "negative_decimal": Decimal("-7.05"),
+        "timestamp": stamp,
+        "array": [1, "two", [3]],
+        "table": {"nested": {"deep": 1}},
+    }
+    message = Message.new(b"", headers=headers)
+    assert message.headers == headers
+    assert Message.new(b"").headers is None
+
+
+def test_message_headers_invalid():
+    with pytest.raises(TypeError):
+        Message.new(b"", headers={"key": object()})
+    with pytest.raises(TypeError):
+        Message.new(b"", headers={1: "value"})
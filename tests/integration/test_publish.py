@@ -1,6 +1,6 @@
 import pytest
 from amqp_rs import AsyncEventbus, Config, ConfigOptions, QoSConfig, ContentEncoding, Message
-from asyncio import Future, sleep, get_running_loop
+from asyncio import Future, sleep, get_running_loop, wait_for
 from json import dumps
 
 
@@ -25,4 +25,75 @@ async def test_subscribe():
     response: Message = future.result()
     assert isinstance(response, Message)
     assert response.body == message
-    await eventbus.dispose()
\ No newline at end of file
+    await eventbus.dispose()
+
+@pytest.mark.asyncio
+async def test_publish_properties():
+    future = Future(loop = get_running_loop())
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    async def handle(body: Message):
+        if not future.done():
+            future.set_result(body)
+
+    exchange_name = "test"
+    routing_key = "test.properties"
+    await eventbus.subscribe(exchange_name, routing_key, handle)
+    await eventbus.publish(exchange_name, routing_key, b"{}", headers={"x-trace": "abc"}, message_id="msg-1", correlation_id="corr-1", priority=3, timestamp=1_700_000_000, app_id="tests", type="test.created", content_encoding=ContentEncoding.Zstd)
+    response: Message = await future
+    assert response.body == b"{}"
+    assert response.headers == {"x-trace": "abc"}
+    assert response.message_id == "msg-1"
+    assert response.correlation_id == "corr-1"
+    assert response.priority == 3
+    assert response.timestamp == 1_700_000_000
+    assert response.app_id == "tests"
+    assert response.type == "test.created"
+    assert response.content_encoding == "zstd"
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_republish_compressed_message():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    first = Future(loop = get_running_loop())
+    second = Future(loop = get_running_loop())
+    async def handle_first(message: Message):
+        if not first.done():
+            first.set_result(message)
+    async def handle_second(message: Message):
+        if not second.done():
+            second.set_result(message)
+
+    body = b'{"rows": [1, 2, 3]}' * 16
+    await eventbus.subscribe("test", "test.compressed.first", handle_first)
+    await eventbus.subscribe("test", "test.compressed.second", handle_second)
+    await eventbus.publish("test", "test.compressed.first", body, content_encoding=ContentEncoding.Lz4)
+    consumed: Message = await wait_for(first, 5)
+    assert (consumed.body, consumed.content_encoding) == (body, "lz4")
+    await eventbus.publish("test", "test.compressed.second", consumed)
+    republished: Message = await wait_for(second, 5)
+    assert (republished.body, republished.content_encoding) == (body, "lz4")
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_publish_message():
+    future = Future(loop = get_running_loop())
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    async def handle(body: Message):
+        if not future.done():
+            future.set_result(body)
+
+    exchange_name = "test"
+    routing_key = "test.message"
+    await eventbus.subscribe(exchange_name, routing_key, handle)
+    await eventbus.publish(exchange_name, routing_key, Message.new(b"payload", content_type="text/plain", headers={"tenant": 7}), message_id="msg-2")
+    response: Message = await future
+    assert response.body == b"payload"
+    assert response.content_type == "text/plain"
+    assert response.headers == {"tenant": 7}
+    assert response.message_id == "msg-2"
+    await eventbus.dispose()
@@ -0,0 +1,41 @@
+import pytest
+from amqp_rs import AsyncEventbus, Config, ConfigOptions, QoSConfig, QueueOptions, QueueType, Overflow, PreconditionFailedError
+from asyncio import Future, get_running_loop, wait_for
+
+
+def new_eventbus():
+    options = ConfigOptions(queue_name='test_options_queue', rpc_exchange_name='test_options_exchange', rpc_queue_name='test_options_rpc_queue')
+    return AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+
+
+@pytest.mark.asyncio
+async def test_subscribe_queue_options():
+    eventbus = new_eventbus()
+    future = Future(loop=get_running_loop())
+    async def handle(message):
+        future.set_result(message.body)
+    queue_options = QueueOptions(durable=False, auto_delete=True, message_ttl=60_000, max_length=10, overflow=Overflow.RejectPublish)
+    await eventbus.subscribe("test_options_exchange", "options.created", handle, queue_options=queue_options)
+    await eventbus.publish("test_options_exchange", "options.created", b"hi")
+    assert await wait_for(future, 5) == b"hi"
+    info = await eventbus.declare_queue("test_options_queue", passive=True)
+    assert info.consumer_count == 1
+    with pytest.raises(PreconditionFailedError):
+        await eventbus.declare_queue("test_options_queue", durable=True)
+    with pytest.raises(PreconditionFailedError, match="test_options_queue"):
+        await eventbus.subscribe("test_options_exchange", "options.updated", handle, queue_options=QueueOptions(durable=False, auto_delete=True))
+    await eventbus.subscribe("test_options_exchange", "options.updated", handle, queue_options=queue_options)
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_provider_queue_options():
+    eventbus = new_eventbus()
+    async def handle(message):
+        return message.body
+    queue_options = QueueOptions(queue_type=QueueType.Quorum, single_active_consumer=True)
+    await eventbus.provide_resource("options.find", handle, queue_options=queue_options)
+    result = await eventbus.rpc_client("test_options_exchange", "options.find", b"hi")
+    assert result == b"hi"
+    await eventbus.delete_queue("test_options_rpc_queue")
+    await eventbus.dispose()
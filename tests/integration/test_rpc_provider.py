@@ -1,7 +1,8 @@
 import pytest
-from amqp_rs import AsyncEventbus, Config, ConfigOptions, QoSConfig, ContentEncoding
-from asyncio import Future, get_running_loop
+from amqp_rs import AsyncEventbus, Config, ConfigOptions, QoSConfig, ContentEncoding, Message, RemoteHandlerError
+from asyncio import Future, ensure_future, get_running_loop, sleep, wait_for
 from json import dumps
+from threading import current_thread, main_thread
 
 
 @pytest.mark.asyncio
@@ -36,11 +37,177 @@ async def test_provider_error():
         raise Exception("errorad", "adasd")
     routing_key = "abc.example"
     body = dumps(["hi"])
-    await eventbus.provide_resource(routing_key, handle)
-    result = await eventbus.rpc_client(options.rpc_exchange_name, routing_key, body, "application/json", ContentEncoding.Null)
+    await eventbus.provide_resource(routing_key, handle, include_traceback=True)
+    with pytest.raises(RemoteHandlerError) as error:
+        await eventbus.rpc_client(options.rpc_exchange_name, routing_key, body, "application/json", ContentEncoding.Null, response_timeout=20_000)
     assert future.done()
     assert future.result() == expected_result
-    print(result)
-    assert result == bytes("Exception: ('errorad', 'adasd')", "utf-8")
+    assert error.value.exception_type == "Exception"
+    assert error.value.message == "('errorad', 'adasd')"
+    assert str(error.value) == "Exception: ('errorad', 'adasd')"
+    assert "raise Exception" in error.value.traceback
     await eventbus.dispose()
     
+
+
+@pytest.mark.asyncio
+async def test_provider_large_error_message():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    message = "x" * 300_000
+    async def handle(_):
+        raise ValueError(message)
+    routing_key = "abc.large_error"
+    await eventbus.provide_resource(routing_key, handle)
+    with pytest.raises(RemoteHandlerError) as error:
+        await eventbus.rpc_client(options.rpc_exchange_name, routing_key, b"{}", "application/json", ContentEncoding.Null, response_timeout=20_000)
+    assert error.value.exception_type == "ValueError"
+    assert error.value.message == message
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_sync_provider_in_executor():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    threads = []
+    def handle(message):
+        threads.append(current_thread())
+        return message.body
+    routing_key = "abc.sync"
+    body = dumps(["hi"])
+    await eventbus.provide_resource(routing_key, handle, run_in_executor=True)
+    result = await eventbus.rpc_client(options.rpc_exchange_name, routing_key, body, "application/json", ContentEncoding.Null)
+    assert result == bytes(body, "utf-8")
+    assert threads[0] is not main_thread()
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_rpc_call_message():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    async def handle(message):
+        return Message.new(b"\x91\x01", content_type="application/msgpack", headers={"x-next-cursor": "abc", "status": 206})
+    routing_key = "abc.message"
+    await eventbus.provide_resource(routing_key, handle)
+    reply = await eventbus.rpc_call(options.rpc_exchange_name, routing_key, dumps(["hi"]))
+    assert reply.body == b"\x91\x01"
+    assert reply.content_type == "application/msgpack"
+    assert reply.headers == {"x-next-cursor": "abc", "status": 206}
+    assert reply.correlation_id is not None
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_rpc_call_message_with_reserved_headers():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    async def handle(message):
+        return Message.new(b"ok", headers={"x-rpc-error": True, "x-rpc-error-type": "ValueError", "status": 200})
+    routing_key = "abc.reserved_headers"
+    await eventbus.provide_resource(routing_key, handle)
+    reply = await eventbus.rpc_call(options.rpc_exchange_name, routing_key, dumps(["hi"]))
+    assert reply.body == b"ok"
+    assert reply.headers == {"status": 200}
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_provider_long_error_type():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    message = "x" * 300_000
+    LongError = type("E" * 300, (Exception,), {})
+    async def handle(_):
+        raise LongError(message)
+    routing_key = "abc.long_error_type"
+    await eventbus.provide_resource(routing_key, handle)
+    with pytest.raises(RemoteHandlerError) as error:
+        await eventbus.rpc_client(options.rpc_exchange_name, routing_key, b"{}", "application/json", ContentEncoding.Null, response_timeout=20_000)
+    assert error.value.exception_type == "E" * 256
+    assert error.value.message == message
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_direct_reply_to():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig(rpc_direct_reply_to=True))
+    async def handle(message):
+        return message.body
+    routing_key = "abc.direct"
+    await eventbus.provide_resource(routing_key, handle)
+    for i in range(3):
+        body = dumps([i])
+        reply = await eventbus.rpc_call(options.rpc_exchange_name, routing_key, body)
+        assert reply.body == bytes(body, "utf-8")
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_rpc_gather():
+    def make_eventbus(rpc_queue_name):
+        options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_gather_exchange', rpc_queue_name=rpc_queue_name)
+        return AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    regions = [make_eventbus(f"test_gather_{region}") for region in ("eu", "us")]
+    for region, eventbus in zip(("eu", "us"), regions):
+        async def handle(message, region=region):
+            return region.encode()
+        await eventbus.provide_resource("stock.check", handle)
+    client = make_eventbus("test_gather_client")
+    replies = await client.rpc_gather("test_gather_exchange", "stock.check", dumps({"sku": "A1"}), timeout=2_000)
+    assert sorted(reply.body for reply in replies) == [b"eu", b"us"]
+    assert len({reply.correlation_id for reply in replies}) == 1
+    first = await client.rpc_gather("test_gather_exchange", "stock.check", dumps({"sku": "A1"}), timeout=2_000, max_replies=1)
+    assert len(first) == 1
+    assert await client.rpc_gather("test_gather_exchange", "nobody.listens", b"{}", timeout=200) == []
+    for eventbus in regions + [client]:
+        await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_rpc_stream():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_stream_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    closed = Future(loop = get_running_loop())
+    async def rows(message):
+        try:
+            for i in range(int(message.body)):
+                yield str(i).encode()
+        finally:
+            if not closed.done():
+                closed.set_result(True)
+    await eventbus.provide_resource("report.rows", rows)
+    chunks = [chunk async for chunk in eventbus.rpc_stream(options.rpc_exchange_name, "report.rows", b"3")]
+    assert [chunk.body for chunk in chunks] == [b"0", b"1", b"2"]
+    assert [chunk.headers["x-stream-seq"] for chunk in chunks] == [0, 1, 2]
+    closed = Future(loop = get_running_loop())
+    stream = eventbus.rpc_stream(options.rpc_exchange_name, "report.rows", b"1000000")
+    assert (await stream.__anext__()).body == b"0"
+    await stream.aclose()
+    assert await wait_for(closed, 5)
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_rpc_stream_cancel_before_first_chunk():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_stream_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    closed = Future(loop = get_running_loop())
+    async def slow_rows(message):
+        try:
+            await sleep(0.2)
+            while True:
+                yield b"row"
+        finally:
+            if not closed.done():
+                closed.set_result(True)
+    await eventbus.provide_resource("report.slow_rows", slow_rows)
+    stream = eventbus.rpc_stream(options.rpc_exchange_name, "report.slow_rows", b"")
+    first = ensure_future(stream.__anext__())
+    await sleep(0.05)
+    await stream.aclose()
+    assert await wait_for(closed, 5)
+    await first
+    await eventbus.dispose()
@@ -1,7 +1,8 @@
 import pytest
-from amqp_rs import AsyncEventbus, Config, ConfigOptions, QoSConfig
-from asyncio import Future, wait_for, get_running_loop
+from amqp_rs import AsyncEventbus, Config, ConfigOptions, QoSConfig, AlreadySettledError
+from asyncio import Future, Queue, TimeoutError, wait_for, get_running_loop, sleep
 from json import dumps
+from threading import current_thread
 
 
 
@@ -30,6 +31,44 @@ async def test_subscribe():
 
 
 
+@pytest.mark.asyncio
+async def test_subscribe_sync_handler_runs_on_the_loop():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig(pub_confirm=True, rpc_client_confirm=True, rpc_server_confirm=True, sub_auto_ack=True, rpc_server_auto_ack=True, rpc_client_auto_ack=True, sub_prefetch=None, rpc_server_prefetch=None, rpc_client_prefetch=None))
+    loop_thread = current_thread()
+    future = Future(loop = get_running_loop())
+
+    def handle(_):
+        if not future.done():
+            future.set_result(current_thread())
+
+    await eventbus.subscribe("example", "sync.loop.example", handle)
+    await eventbus.publish("example", "sync.loop.example", dumps(["hi"]))
+    assert await wait_for(future, timeout=1) is loop_thread
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_subscribe_same_key_on_two_exchanges():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    received = {"orders": Future(loop = get_running_loop()), "invoices": Future(loop = get_running_loop())}
+
+    def handler(exchange_name):
+        async def handle(message):
+            if not received[exchange_name].done():
+                received[exchange_name].set_result(message.delivery_info.exchange)
+        return handle
+
+    for exchange_name in received:
+        await eventbus.subscribe(exchange_name, "created.#", handler(exchange_name))
+    await eventbus.publish("invoices", "created.eu", dumps(["hi"]))
+    await eventbus.publish("orders", "created.eu", dumps(["hi"]))
+    assert await wait_for(received["orders"], timeout=5) == "orders"
+    assert await wait_for(received["invoices"], timeout=5) == "invoices"
+    await eventbus.dispose()
+
+
 @pytest.mark.asyncio
 async def test_subscribe_topic():
     options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
@@ -46,4 +85,130 @@ async def test_subscribe_topic():
     await eventbus.subscribe(exchange_name, routing_key, handle)
     await eventbus.publish(exchange_name, "abc.example", body, command_timeout=2)
     assert not future.done()
-    await eventbus.dispose()
\ No newline at end of file
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_subscribe_delivery_info():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig(pub_confirm=True, rpc_client_confirm=True, rpc_server_confirm=True, sub_auto_ack=False, rpc_server_auto_ack=True, rpc_client_auto_ack=True, sub_prefetch=None, rpc_server_prefetch=None, rpc_client_prefetch=None))
+    future = Future(loop = get_running_loop())
+
+    async def handle(message):
+        if not future.done():
+            future.set_result(message)
+
+    exchange_name = "example"
+    await eventbus.subscribe(exchange_name, "orders.*", handle)
+    await eventbus.publish(exchange_name, "orders.created", dumps(["hi"]))
+    message = await wait_for(future, timeout=1)
+    assert message.delivery_info.exchange == exchange_name
+    assert message.delivery_info.routing_key == "orders.created"
+    assert message.delivery_info.redelivered is False
+    assert message.delivery_info.delivery_tag > 0
+    assert message.delivery_info.consumer_tag
+    await eventbus.dispose()
+
+
+
+@pytest.mark.asyncio
+async def test_subscribe_manual_ack():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig(pub_confirm=True, rpc_client_confirm=True, rpc_server_confirm=True, sub_auto_ack=False, rpc_server_auto_ack=True, rpc_client_auto_ack=True, sub_prefetch=None, rpc_server_prefetch=None, rpc_client_prefetch=None))
+    future = Future(loop = get_running_loop())
+
+    async def handle(message):
+        await message.ack()
+        try:
+            await message.ack()
+        except AlreadySettledError as error:
+            if not future.done():
+                future.set_result(error)
+
+    exchange_name = "example"
+    await eventbus.subscribe(exchange_name, "manual.ack", handle)
+    await eventbus.publish(exchange_name, "manual.ack", dumps(["hi"]))
+    error = await wait_for(future, timeout=1)
+    assert isinstance(error, AlreadySettledError)
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_subscription_handle():
+    options = ConfigOptions(queue_name='test_subscription_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    received = Queue()
+
+    async def handle(message):
+        await received.put(message.body)
+        if message.body == b"fail":
+            raise ValueError("fail")
+
+    exchange_name = "example"
+    subscription = await eventbus.subscribe(exchange_name, "subscription.handle", handle)
+    assert subscription.queue_name == 'test_subscription_queue'
+    assert subscription.consumer_tag
+    await eventbus.publish(exchange_name, "subscription.handle", b"ok")
+    await eventbus.publish(exchange_name, "subscription.handle", b"fail")
+    assert await wait_for(received.get(), timeout=2) == b"ok"
+    assert await wait_for(received.get(), timeout=2) == b"fail"
+    await sleep(0.2)
+    assert (subscription.delivered, subscription.handled, subscription.failed) == (2, 1, 1)
+
+    await subscription.pause()
+    assert subscription.paused and subscription.consumer_tag is None
+    await eventbus.publish(exchange_name, "subscription.handle", b"later")
+    with pytest.raises(TimeoutError):
+        await wait_for(received.get(), timeout=0.5)
+    await subscription.resume()
+    assert await wait_for(received.get(), timeout=2) == b"later"
+
+    await subscription.cancel()
+    assert subscription.cancelled
+    await eventbus.delete_queue('test_subscription_queue')
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_consume_iterator():
+    options = ConfigOptions(queue_name='test_consume_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    exchange_name = "example"
+    stream = eventbus.consume(exchange_name, "consume.iterator", prefetch=2)
+    iterator = stream.__aiter__()
+    first = wait_for(iterator.__anext__(), timeout=2)
+    await sleep(0.2)
+    for body in (b"one", b"two", b"three"):
+        await eventbus.publish(exchange_name, "consume.iterator", body)
+    received = []
+    message = await first
+    while True:
+        received.append(message.body)
+        await message.ack()
+        if len(received) == 3:
+            break
+        message = await wait_for(iterator.__anext__(), timeout=2)
+    assert received == [b"one", b"two", b"three"]
+    await stream.aclose()
+    with pytest.raises(StopAsyncIteration):
+        await stream.__anext__()
+    await eventbus.delete_queue('test_consume_queue')
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_sync_handler():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    eventbus = AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+    loop = get_running_loop()
+    future = Future(loop=loop)
+
+    def handle(message):
+        if not future.done():
+            future.set_result(message.body)
+
+    subscription = await eventbus.subscribe("example", "sync.handler", handle)
+    await eventbus.publish("example", "sync.handler", b"plain")
+    assert await wait_for(future, timeout=1) == b"plain"
+    await subscription.cancel()
+    await eventbus.dispose()
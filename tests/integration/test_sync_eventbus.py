@@ -0,0 +1,61 @@
+from threading import Event, current_thread
+from json import dumps
+from amqp_rs import SyncEventbus, Config, ConfigOptions, QoSConfig, AlreadySettledError
+
+
+def make_eventbus(qos_config=None):
+    options = ConfigOptions(queue_name='test_sync_queue', rpc_exchange_name='test_sync_exchange', rpc_queue_name='test_sync_rpc_queue')
+    return SyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), qos_config or QoSConfig.default()), options
+
+
+def test_sync_subscribe():
+    eventbus, _ = make_eventbus()
+    received = Event()
+    threads = []
+
+    def handle(message):
+        threads.append(current_thread().name)
+        received.set()
+
+    subscription = eventbus.subscribe("example", "sync.example", handle)
+    eventbus.publish("example", "sync.example", dumps(["hi"]))
+    assert received.wait(timeout=1)
+    assert threads[0].startswith("amqp-rs-handler-")
+    assert subscription.cancel() is None
+    assert subscription.cancelled
+    eventbus.dispose()
+
+
+def test_sync_manual_ack():
+    eventbus, _ = make_eventbus(QoSConfig(pub_confirm=True, rpc_client_confirm=True, rpc_server_confirm=True, sub_auto_ack=False, rpc_server_auto_ack=True, rpc_client_auto_ack=True, sub_prefetch=None, rpc_server_prefetch=None, rpc_client_prefetch=None))
+    settled = Event()
+    results = []
+
+    def handle(message):
+        results.append(message.ack())
+        try:
+            message.ack()
+        except AlreadySettledError as error:
+            results.append(error)
+        settled.set()
+
+    subscription = eventbus.subscribe("example", "sync.ack", handle)
+    eventbus.publish("example", "sync.ack", dumps(["hi"]))
+    assert settled.wait(timeout=1)
+    assert results[0] is None
+    assert isinstance(results[1], AlreadySettledError)
+    subscription.cancel()
+    eventbus.dispose()
+
+
+def test_sync_provider():
+    eventbus, options = make_eventbus()
+
+    def handle(message):
+        return message.body
+
+    eventbus.provide_resource("sync.rpc", handle)
+    body = dumps(["hi"])
+    result = eventbus.rpc_client(options.rpc_exchange_name, "sync.rpc", body)
+    assert result == bytes(body, "utf-8")
+    eventbus.dispose()
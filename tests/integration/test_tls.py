@@ -1,5 +1,5 @@
 import pytest
-from amqp_rs import Config, ConfigOptions, AsyncEventbus, QoSConfig, TlsAdaptor
+from amqp_rs import AuthMechanism, Config, ConfigOptions, AsyncEventbus, QoSConfig, TlsAdaptor, TrustRoots
 import asyncio
 
 @pytest.mark.asyncio
@@ -14,4 +14,48 @@ async def test_tls():
     async def handler(_):
         pass
     await eventbus.subscribe(exchange_name, routing_key, handler, None, None)
-    await eventbus.dispose()
\ No newline at end of file
+    await eventbus.dispose()
+
+@pytest.mark.asyncio
+async def test_external_auth():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    with pytest.raises(ValueError, match="EXTERNAL"):
+        Config(host='localhost', port=5671, username='', password='', virtual_host='/', options=options, auth_mechanism=AuthMechanism.External)
+    config = Config.from_url("amqps://localhost?auth_mechanism=external&cacertfile=./.certs/amqp/ca.pem&certfile=./.certs/amqp/rabbitmq_cert.pem&keyfile=./.certs/amqp/rabbitmq_key.pem", options)
+    assert config.auth_mechanism == AuthMechanism.External
+    tls_adaptor = TlsAdaptor.with_client_auth("./.certs/amqp/ca.pem", "./.certs/amqp/rabbitmq_cert.pem", "./.certs/amqp/rabbitmq_key.pem", "localhost")
+    without_credentials = Config(host='localhost', port=5671, username=None, password=None, virtual_host='/', options=options, tls_adaptor=tls_adaptor, auth_mechanism=AuthMechanism.External)
+    assert (without_credentials.username, without_credentials.password) == ("", "")
+    eventbus = AsyncEventbus(config, QoSConfig.default())
+    await eventbus.publish("amq.topic", "external.check", b"{}")
+    await eventbus.dispose()
+
+@pytest.mark.asyncio
+async def test_tls_from_pem():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    with open("./.certs/amqp/ca.pem") as f:
+        ca_pem = f.read()
+    with open("./.certs/amqp/rabbitmq_cert.pem", "rb") as f:
+        cert_pem = f.read()
+    with open("./.certs/amqp/rabbitmq_key.pem", "rb") as f:
+        key_pem = f.read()
+    with pytest.raises(ValueError, match="together"):
+        TlsAdaptor.from_pem(ca_pem, cert_pem, domain="localhost")
+    with pytest.raises(ValueError, match="private key"):
+        TlsAdaptor.from_pem(ca_pem, cert_pem, cert_pem, domain="localhost")
+    tls_adaptor = TlsAdaptor.from_pem(ca_pem, cert_pem, key_pem, domain="localhost")
+    config = Config(host='localhost', port=5671, username='', password='', virtual_host='/', options=options, tls_adaptor=tls_adaptor, auth_mechanism=AuthMechanism.External)
+    eventbus = AsyncEventbus(config, QoSConfig.default())
+    await eventbus.publish("amq.topic", "pem.check", b"{}")
+    await eventbus.dispose()
+
+@pytest.mark.asyncio
+async def test_tls_trust_roots():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    with pytest.raises(ValueError, match="no trusted root"):
+        TlsAdaptor.without_client_auth(None, "localhost", roots=[])
+    tls_adaptor = TlsAdaptor.with_client_auth("./.certs/amqp/ca.pem", "./.certs/amqp/rabbitmq_cert.pem", "./.certs/amqp/rabbitmq_key.pem", "localhost", roots=[TrustRoots.Native, TrustRoots.Webpki])
+    config = Config(host='localhost', port=5671, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=tls_adaptor)
+    eventbus = AsyncEventbus(config, QoSConfig.default())
+    await eventbus.publish("amq.topic", "roots.check", b"{}")
+    await eventbus.dispose()
@@ -0,0 +1,60 @@
+import pytest
+from amqp_rs import AsyncEventbus, Config, ConfigOptions, QoSConfig, ExchangeType, NotFoundError, PreconditionFailedError
+from asyncio import Future, get_running_loop, wait_for
+
+
+def new_eventbus():
+    options = ConfigOptions(queue_name='test_queue', rpc_exchange_name='test_exchange', rpc_queue_name='test_rpc_queue')
+    return AsyncEventbus(Config(host='localhost', port=5672, username='guest', password='guest', virtual_host='/', options=options, tls_adaptor=None), QoSConfig.default())
+
+
+@pytest.mark.asyncio
+async def test_topology():
+    eventbus = new_eventbus()
+    await eventbus.declare_exchange("topology.source", "fanout", durable=False, auto_delete=False)
+    await eventbus.declare_exchange("topology.destination", "direct", durable=False)
+    await eventbus.bind_exchange("topology.destination", "topology.source")
+    info = await eventbus.declare_queue("topology.queue", durable=False, arguments={"x-max-length": 10})
+    assert info.name == "topology.queue"
+    await eventbus.bind_queue("topology.queue", "topology.destination", "key")
+    await eventbus.publish("topology.destination", "key", b"hi", command_timeout=2)
+    info = await eventbus.declare_queue("topology.queue", passive=True)
+    assert info.message_count == 1
+    assert info.consumer_count == 0
+    assert await eventbus.purge_queue("topology.queue") == 1
+    await eventbus.unbind_queue("topology.queue", "topology.destination", "key")
+    await eventbus.unbind_exchange("topology.destination", "topology.source")
+    assert await eventbus.delete_queue("topology.queue") == 0
+    await eventbus.delete_exchange("topology.source")
+    await eventbus.delete_exchange("topology.destination")
+    with pytest.raises(NotFoundError):
+        await eventbus.declare_queue("topology.queue", passive=True)
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_declare_conflict():
+    eventbus = new_eventbus()
+    await eventbus.declare_exchange("topology.conflict", "direct", durable=False)
+    with pytest.raises(PreconditionFailedError):
+        await eventbus.declare_exchange("topology.conflict", "fanout", durable=False)
+    await eventbus.delete_exchange("topology.conflict")
+    await eventbus.dispose()
+
+
+@pytest.mark.asyncio
+async def test_exchange_type():
+    eventbus = new_eventbus()
+    future = Future(loop=get_running_loop())
+    async def handle(message):
+        future.set_result(message.delivery_info.routing_key)
+    await eventbus.subscribe("topology.fanout", "ignored", handle, exchange_type=ExchangeType.Fanout)
+    await eventbus.publish("topology.fanout", "any.key", b"hi", exchange_type=ExchangeType.Fanout)
+    assert await wait_for(future, 5) == "any.key"
+    with pytest.raises(PreconditionFailedError):
+        await eventbus.publish("topology.fanout", "any.key", b"hi", exchange_type=ExchangeType.Direct)
+    with pytest.raises(PreconditionFailedError):
+        await eventbus.subscribe("topology.fanout", "key", handle, exchange_type="direct")
+    await eventbus.unbind_queue("test_queue", "topology.fanout", "ignored")
+    await eventbus.delete_exchange("topology.fanout")
+    await eventbus.dispose()